@@ -26,7 +26,7 @@ if control is not None:
     time.sleep(3)
     img = cam.poll_frame_pil()
     img.save("img_bri_0.7.png")
-    control.set_value(None) # Reset
+    control.set_value(control.default) # Reset
 
 
 # White balance doesn't seem to work for me
@@ -38,4 +38,4 @@ if control is not None:
     time.sleep(3)
     img = cam.poll_frame_pil()
     img.save("img_bal_3000.png")
-    control.set_value(None) # Reset
\ No newline at end of file
+    control.set_value(control.default) # Reset
\ No newline at end of file
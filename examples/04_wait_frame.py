@@ -0,0 +1,13 @@
+"""
+Wait for new frames instead of polling.
+Note: example requires pillow to be installed.
+"""
+import omni_camera
+cam = omni_camera.Camera(omni_camera.query()[0]) # Open a camera
+for i in range(10):
+    img = cam.wait_frame_pil(timeout=5) # Blocks until a new frame arrives
+    if img is None:
+        print("Timed out")
+        break
+    print(f"Frame {i}: {img.size}")
+img.save("img.png")
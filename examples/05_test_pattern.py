@@ -0,0 +1,11 @@
+"""
+Capture from a virtual test pattern camera, no hardware required.
+Note: example requires pillow to be installed.
+"""
+import omni_camera
+cam = omni_camera.Camera(omni_camera.query(test_patterns=True)[-1]) # Test patterns are listed after real cameras
+fmt = cam.get_format_options().prefer_width_range(min_width=1280).prefer_frame_format(omni_camera.FrameFormat.YUYV).resolve()
+cam.open(fmt)
+cam.get_controls()["Brightness"].set_value(40)
+img = cam.wait_frame_pil(timeout=5)
+img.save("test_pattern.png")
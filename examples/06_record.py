@@ -0,0 +1,9 @@
+"""
+Record 5 seconds of video to an AVI file.
+"""
+import omni_camera
+import time
+cam = omni_camera.Camera(omni_camera.query()[0]) # Open a camera
+cam.start_recording("video.avi") # Frames are written in background
+time.sleep(5)
+cam.stop_recording()
@@ -0,0 +1,14 @@
+"""
+Keep capturing while the camera is unplugged and plugged back in.
+"""
+import omni_camera
+cam = omni_camera.Camera(omni_camera.query()[0]) # Open a camera
+cam.open(auto_reconnect=True, reconnect_interval=0.5)
+state = None
+while True:
+    frame = cam.wait_frame(timeout=1)
+    if cam.state != state:
+        state = cam.state
+        print(f"Camera is {state.value}")
+    if frame is not None:
+        print(f"Frame {frame.sequence}", end="\r")
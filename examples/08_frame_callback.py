@@ -0,0 +1,15 @@
+"""
+Process frames as they arrive instead of polling for them.
+"""
+import time
+import omni_camera
+cam = omni_camera.Camera(omni_camera.query()[0]) # Open a camera
+
+def on_frame(frame: omni_camera.Frame):
+    print(f"Frame {frame.sequence}: {frame.width}x{frame.height}, {frame.age * 1000:.1f} ms old")
+
+callback_id = cam.add_frame_callback(on_frame) # Called from a separate thread
+cam.open()
+time.sleep(5)
+cam.remove_frame_callback(callback_id)
+cam.close()
@@ -0,0 +1,17 @@
+"""
+Receive frames in an asyncio application without blocking the event loop.
+"""
+import asyncio
+import omni_camera
+
+async def main():
+    cam = omni_camera.Camera(omni_camera.query()[0]) # Open a camera
+    frame = await cam.next_frame_async(timeout=5)
+    print(f"First frame: {frame}")
+    async with cam.frames() as frames:
+        async for frame in frames:
+            print(f"Frame {frame.sequence}", end="\r")
+            if frame.sequence >= 100:
+                break
+
+asyncio.run(main())
@@ -0,0 +1,12 @@
+"""
+Capture matching frames from two cameras, e.g. a stereo pair.
+"""
+import omni_camera
+cameras = [omni_camera.Camera(info) for info in omni_camera.query()[:2]]
+with omni_camera.CameraGroup(cameras, tolerance=0.02) as group:
+    for i in range(10):
+        frames = group.wait_frameset(timeout=5)
+        if frames is None:
+            print("Timed out")
+            break
+        print(f"Set {i}: sequences {[frame.sequence for frame in frames]}, skew {frames.skew * 1000:.1f} ms")
@@ -0,0 +1,16 @@
+"""
+Print cameras as they are connected and disconnected.
+"""
+import omni_camera
+
+with omni_camera.DeviceWatcher() as watcher:
+    for cam in watcher.cameras:
+        print(f"Connected: {cam.name} ({cam.stable_id})")
+    print("Plug or unplug a camera, Ctrl+C to quit")
+    try:
+        while True:
+            event = watcher.wait_event()
+            if event is not None:
+                print(f"{event.kind.capitalize()}: {event.camera.name} ({event.camera.stable_id})")
+    except KeyboardInterrupt:
+        pass
@@ -1,8 +1,21 @@
-from dataclasses import dataclass
+import asyncio
+from dataclasses import dataclass, field
 from enum import Enum
-from typing import Dict, List, Union
+from pathlib import Path
+from typing import Any, Callable, Dict, List, Optional, Union
 import warnings
+import weakref
 from . import omni_camera
+from . import settings
+from .omni_camera import (
+    CameraError,
+    DeviceNotFoundError,
+    PermissionDeniedError,
+    DeviceBusyError,
+    UnsupportedFormatError,
+    StreamClosedError,
+    ControlError,
+)
 import sys
 try:
     import numpy as np
@@ -14,26 +27,95 @@ try:
 except ImportError:
     print("[OmniCamera] Could not import pillow", file=sys.stderr)
 
+Frame = omni_camera.CamFrame
+"""
+A captured frame. Has width, height, data (pixel values), pixel_format, sequence (increases by one for every
+captured frame), timestamp (wall-clock seconds since the unix epoch), monotonic (seconds since the
+module was loaded, unaffected by clock adjustments) and age (seconds since capture) attributes.
+raw_data and raw_format hold undecoded data if the camera was opened with keep_raw=True.
+numpy.asarray(frame) gives a read-only array sharing memory with the frame.
+"""
+
+CaptureStats = omni_camera.CaptureStats
+"""
+Capture statistics since the camera was opened. Counters: frames_captured, frames_dropped (evicted from a full queue),
+frames_overwritten (replaced by a newer frame before any consumer took them: poll_frame*/wait_frame*,
+the queue, frame callbacks or frame streams), read_errors,
+decode_errors and last_error (description of the most recent failure, or None).
+Measured over the last 60 frames, in seconds: fps, interval_jitter (standard deviation of the time between frames)
+and decode_time (average), None until enough frames arrived.
+"""
+
+
 @dataclass
 class CameraInfo:
     """
     Describes a connected camera.
+    *index* is None for cameras the backend identifies by a string (see id).
+    *backend* is the backend which listed the camera and is used to open it, see backends().
+    *stable_id* identifies the camera even after it's replugged and got another index,
+    it's based on USB vendor/product ids and serial number (or USB port) where available.
     """
-    index: int
+    index: Optional[int]
     name: str
     description: str
     misc: str
+    id: str = ""
+    backend: str = ""
+    path: Optional[str] = None
+    vendor_id: Optional[int] = None
+    product_id: Optional[int] = None
+    serial: Optional[str] = None
+    bus: Optional[str] = None
+    stable_id: str = ""
+    _device: Any = field(default=None, repr=False, compare=False)
+
+    @classmethod
+    def _from_device(cls, device) -> "CameraInfo":
+        return cls(device.index, device.name, device.description, device.misc, device.id, device.backend,
+                   device.path, device.vendor_id, device.product_id, device.serial, device.bus, device.stable_id,
+                   device)
+
+    def _target(self):
+        return self._device if self._device is not None else self.index
 
     def can_open(self):
         """
         Check if this camera can be opened.
         """
-        return omni_camera.check_can_use(self.index)
+        return omni_camera.check_can_use(self._target())
 
 
 class FrameFormat(Enum):
     MJPEG = "mjpeg"
     YUYV = "yuyv"
+    GRAY = "gray"
+    NV12 = "nv12"
+    RAWRGB = "rawrgb"
+
+
+class PixelFormat(Enum):
+    """
+    Layout of decoded frames.
+    """
+    RGB = "rgb"
+    BGR = "bgr"
+    RGBA = "rgba"
+    LUMA = "luma"
+    I420 = "i420"
+    """Planar YUV 4:2:0: full resolution Y plane followed by quarter resolution U and V planes."""
+
+
+class StreamState(Enum):
+    """
+    What capture is currently doing, see Camera.state.
+    """
+    CLOSED = "closed"
+    STREAMING = "streaming"
+    DISCONNECTED = "disconnected"
+    """The camera was unplugged (or stopped working). Capture has stopped, unless it was opened with auto_reconnect."""
+    RECONNECTING = "reconnecting"
+    """Waiting for the camera to come back, see Camera.open."""
 
 
 class CameraFormat:
@@ -129,11 +211,49 @@ class CameraFormatOptions(list):
 class CameraControl:
     def __init__(self, control):
         self._control = control
+
+    @property
+    def kind(self) -> str:
+        """
+        Type of values this control accepts, one of 'integer', 'integer_range', 'float', 'float_range',
+        'boolean', 'string', 'bytes', 'key_value', 'point', 'enum', 'rgb', 'none'.
+        """
+        return self._control.kind()
+
+    @property
+    def value(self) -> Any:
+        """
+        Current value, read from the camera. Its type depends on kind, see set_value.
+        """
+        return self._control.value()
+
+    @property
+    def default(self) -> Any:
+        """
+        Default value, of the same type as value.
+        """
+        return self._control.default()
+
+    @property
+    def is_active(self) -> bool:
+        """
+        Whether the control is active (i.e. not in automatic mode), read from the camera.
+        """
+        return self._control.is_active()
+
+    @property
+    def flags(self) -> list[str]:
+        """
+        Flags reported by the camera, any of 'automatic', 'manual', 'continuous', 'read_only',
+        'write_only', 'volatile', 'disabled'.
+        """
+        return self._control.flags
     
     @property
     def value_range(self) -> range:
         """
         Return a range of values that can be passed to set_value.
+        Only available for 'integer_range' controls.
         """
         start, stop, step = self._control.value_range()
         # Just in case - ensure that start is divisible by step, as required by nokhwa
@@ -142,12 +262,32 @@ class CameraControl:
             new_start += step
         return range(start, stop, step)
 
-    def set_value(self, value: Union[int, None]):
+    @property
+    def float_range(self) -> tuple[float, float, float]:
         """
-        Set a value for this control.
-        *value* in self.value_range should be true.
+        Return (min, max, step) of a 'float_range' control.
+        """
+        return self._control.float_range()
+
+    @property
+    def menu_items(self) -> Dict[int, str]:
+        """
+        Return a dictionary of menu item values to their labels for an 'enum' control.
+        Labels are only known for some common controls (e.g. power line frequency), otherwise the value is used as label.
+        """
+        return dict(self._control.menu_items())
+
+    def set_value(self, value: Any):
         """
-        if value is not None:
+        Set a value for this control, its type depends on kind:
+        int for 'integer'/'integer_range', float for 'float'/'float_range', bool, str, bytes,
+        (key, value) for 'key_value', (x, y) for 'point', (r, g, b) for 'rgb',
+        and menu item value or label for 'enum'.
+        For 'integer_range' controls, *value* in self.value_range should be true.
+        None (automatic mode) raises ControlError, as backends can't switch individual controls to automatic mode.
+        Use the camera's dedicated control for that instead, e.g. 'Exposure, Auto'.
+        """
+        if value is not None and self.kind == "integer_range":
             assert value in self.value_range
         self._control.set_value(value)
 
@@ -155,21 +295,55 @@ class CameraControl:
         """
         Set a value for this control.
         0 <= *fraction* <= 1 should be true.
+        Only available for 'integer_range' and 'float_range' controls.
         """
         assert 0 <= fraction <= 1
+        if self.kind == "float_range":
+            start, stop, step = self.float_range
+            value = start + fraction * (stop - start)
+            if step > 0:
+                value = start + round((value - start) / step) * step
+            self.set_value(min(value, stop))
+            return
         ind = round(fraction * (len(self.value_range)-1))
         self.set_value(self.value_range[ind])
 
 
 class Camera:
-    def __init__(self, info: CameraInfo, suggested_fps: int = 25):
+    def __init__(self, info: Union[CameraInfo, int, str], suggested_fps: int = 25, backend: str = "auto"):
         """
         Open a camera corresponding to the info object.
+        Cameras can also be given by index, or by a string: a device path such as /dev/video0,
+        a stable id (see CameraInfo), a backend specific identifier (e.g. for IP cameras) or a camera name.
+        These are opened with *backend* (see backends()), cameras given by CameraInfo use the backend which listed them.
         Will try to use maximum possible resolution with a frame rate of at least *suggested_fps*
         """
-        self.info = info
+        if isinstance(info, CameraInfo):
+            self.info = info
+            target = info._target()
+        else:
+            self.info = None
+            target = info
         self._initialized = False
-        self._cam = omni_camera.Camera(info.index)
+        self._async_stream = None
+        self._cam = omni_camera.Camera(target, backend)
+
+    @classmethod
+    def from_file(cls, path, loop: bool = False, frame_rate: int = 30, resolution: Union[tuple[int, int], None] = None) -> "Camera":
+        """
+        Create a camera which replays recorded footage instead of capturing from a device.
+        *path* can be a directory of PNG/JPEG frames (played in file name order), an MJPEG stream,
+        or a raw rgb dump, in which case *resolution* (width, height) has to be specified.
+        Frames are delivered at *frame_rate*, and playback restarts from the beginning if *loop* is true.
+        Otherwise capture stops at the end of the recording without an error: state becomes closed,
+        wait_frame returns None, queued frames can still be read and frame streams end. Opening it again replays it.
+        """
+        cam = cls.__new__(cls)
+        cam.info = None
+        cam._initialized = False
+        cam._async_stream = None
+        cam._cam = omni_camera.Camera.from_file(str(path), loop, frame_rate, resolution)
+        return cam
     
     def get_format_options(self) -> CameraFormatOptions:
         """
@@ -184,21 +358,86 @@ class Camera:
         """
         return {k: CameraControl(v) for k, v in self._cam.get_controls()}
 
-    def open(self, fmt: CameraFormat = None):
+    def get_format(self) -> CameraFormat:
+        """
+        Returns the currently selected format.
+        """
+        return CameraFormat(self._cam.get_format())
+
+    def export_settings(self, path: Union[str, Path, None] = None) -> Dict[str, Any]:
+        """
+        Collect the selected format and the value of every control into a versioned dictionary.
+        If *path* is given, settings are also written to it, as TOML if it ends with .toml and as JSON otherwise.
+        """
+        result = settings.export_settings(self)
+        if path is not None:
+            settings.save_settings(result, path)
+        return result
+
+    def apply_settings(self, path_or_dict: Union[str, Path, Dict[str, Any]]) -> Dict[str, Exception]:
+        """
+        Apply settings produced by export_settings, either a dictionary or a path to a saved file.
+        Returns a dictionary of errors, keyed by control name (or "format"), empty if everything was applied.
+        """
+        if not isinstance(path_or_dict, dict):
+            path_or_dict = settings.load_settings(path_or_dict)
+        return settings.apply_settings(self, path_or_dict)
+
+    def open(self, fmt: CameraFormat = None, keep_raw: bool = False, decode: bool = True, pixel_format: PixelFormat = PixelFormat.RGB, queue_size: int = 0,
+             auto_reconnect: bool = False, reconnect_interval: float = 1.0, max_errors: int = 30):
         """
-        Select a format and open the camera.
+        Select a format and open the camera, raises if it can't be opened in that format.
         Called automatically if needed from poll_frame_* method family.
+        Frames are decoded to *pixel_format*, note that poll_frame_pil doesn't support I420.
+        If *keep_raw* is true, frames also carry undecoded data as received from the camera
+        (see Frame.raw_data and Frame.raw_format). Decoding to rgb can be skipped entirely
+        with *decode* set to false, in which case only raw data is available, so *keep_raw* is required
+        (ValueError is raised otherwise).
+        With a non-zero *queue_size*, every captured frame is also queued (up to *queue_size* of them),
+        to be consumed in order with read_frames/next_frame.
+        The camera is considered disconnected once its device is gone or after *max_errors* frames failed
+        to read in a row. Capture then stops and the error is raised by the following poll_frame*/wait_frame* call,
+        unless *auto_reconnect* is set, in which case every *reconnect_interval* seconds an attempt is made
+        to reopen the same device with the same format and controls (see state).
+        If the camera is already open, capture is restarted with the new settings.
         """
-        if self._initialized:
-            raise RuntimeError("Can only open once")
         if fmt is None:
             fmt = self.get_format_options().resolve_default()
-        self._cam.open(fmt._fmt)
+        self._cam.open(fmt._fmt, keep_raw, decode, PixelFormat(pixel_format).value, queue_size,
+                       max_errors, reconnect_interval if auto_reconnect else None)
         self._initialized = True
 
-    def poll_frame_raw(self) -> Union[tuple[int, int, bytes], None]:
+    def set_format(self, fmt: CameraFormat, wait: bool = False):
+        """
+        Switch to another format without restarting capture, e.g. from a low resolution preview
+        to a full resolution still. Opens the camera if it isn't open yet.
+        The switch happens in background, failures are raised by the following poll_frame*/wait_frame* calls,
+        in which case the previous format stays in use. With *wait*, this blocks until the switch is done
+        and raises if it failed instead.
         """
-        Get a frame from the camera. Returns width, height, and array of raw rgb values.
+        if not self._initialized:
+            self.open(fmt)
+            return
+        self._cam.set_format(fmt._fmt, wait)
+
+    def close(self):
+        """
+        Stop capturing and release the device. The camera can be opened again afterwards.
+        Note that poll_frame*/wait_frame* methods open the camera again automatically.
+        """
+        self._initialized = False
+        self._cam.close()
+
+    def __enter__(self) -> "Camera":
+        return self
+
+    def __exit__(self, *exc_info):
+        self.close()
+
+    def poll_frame(self) -> Union["Frame", None]:
+        """
+        Get a frame from the camera. Returns a Frame object, which carries the image along with
+        its sequence number and capture timestamps.
         Guaranteed to never block, but may return None if no frames were received from camera yet.
         """
         if not self._initialized:
@@ -206,38 +445,481 @@ class Camera:
         self._cam.check_err()
         return self._cam.poll_frame()
 
+    def poll_frame_raw(self) -> Union[tuple[int, int, bytes], None]:
+        """
+        Get a frame from the camera. Returns width, height, and array of pixel values (rgb by default).
+        Guaranteed to never block, but may return None if no frames were received from camera yet.
+        """
+        return _frame_to_raw(self.poll_frame())
+
     def poll_frame_np(self) -> Union["np.ndarray", None]:
         """
-        Get a frame from the camera. Returns a numpy array.
+        Get a frame from the camera. Returns a read-only numpy array, no copy of the frame is made.
         Guaranteed to never block, but may return None if no frames were received from camera yet.
         """
-        frame = self.poll_frame_raw()
-        if frame is None:
-            return None
-        w, h, data = frame
-        shape = (h, w, 3)
-        arr = np.frombuffer(data, dtype=np.uint8)
-        return arr.reshape(shape)
+        return _frame_to_np(self.poll_frame())
     
     def poll_frame_pil(self) -> Union["Image.Image", None]:
         """
         Get a frame from the camera. Returns a pillow image.
         Guaranteed to never block, but may return None if no frames were received from camera yet.
         """
-        frame = self.poll_frame_raw()
-        if frame is None:
-            return None
-        return Image.frombytes("RGB", (frame[0], frame[1]), frame[2])
+        return _frame_to_pil(self.poll_frame())
+
+    def wait_frame(self, timeout: Union[float, None] = None) -> Union["Frame", None]:
+        """
+        Wait for a frame newer than the last one returned by poll_frame*/wait_frame* methods.
+        Returns a Frame object, or None if *timeout* seconds have passed.
+        """
+        if not self._initialized:
+            self.open()
+        self._cam.check_err()
+        frame = self._cam.wait_frame(timeout)
+        self._cam.check_err()
+        return frame
+
+    def wait_frame_raw(self, timeout: Union[float, None] = None) -> Union[tuple[int, int, bytes], None]:
+        """
+        Like wait_frame, but returns width, height, and array of pixel values (rgb by default).
+        """
+        return _frame_to_raw(self.wait_frame(timeout))
+
+    def wait_frame_np(self, timeout: Union[float, None] = None) -> Union["np.ndarray", None]:
+        """
+        Like wait_frame, but returns a numpy array.
+        """
+        return _frame_to_np(self.wait_frame(timeout))
+
+    def wait_frame_pil(self, timeout: Union[float, None] = None) -> Union["Image.Image", None]:
+        """
+        Like wait_frame, but returns a pillow image.
+        """
+        return _frame_to_pil(self.wait_frame(timeout))
     
+    def read_frames(self) -> list["Frame"]:
+        """
+        Return all queued frames, oldest first, and clear the queue.
+        Requires the camera to be opened with a non-zero queue_size, raises ValueError otherwise.
+        Frames left in the queue can still be read after the camera is closed or capture failed,
+        the error which stopped capture (or StreamClosedError) is raised after that.
+        """
+        return self._cam.read_frames()
+
+    def next_frame(self, timeout: Union[float, None] = None) -> Union["Frame", None]:
+        """
+        Remove the oldest frame from the queue, waiting for one if the queue is empty.
+        Returns None if *timeout* seconds have passed or capture stopped.
+        Requires the camera to be opened with a non-zero queue_size, see read_frames.
+        """
+        return self._cam.next_frame(timeout)
+
+    @property
+    def state(self) -> StreamState:
+        """
+        Whether the camera is streaming, disconnected or being reconnected.
+        """
+        return StreamState(self._cam.state())
+
+    def add_frame_callback(self, callback: Callable[[Frame], Any], block: bool = False, queue_size: int = 4,
+                           on_stop: Optional[Callable[[], Any]] = None) -> int:
+        """
+        Call *callback* with every new frame, from a thread of its own (the camera still needs to be opened).
+        Up to *queue_size* frames wait while the callback is busy, further ones are skipped,
+        unless *block* is true, in which case capture waits for the callback to catch up,
+        so the callback can't close or reopen the camera (CameraError is raised if it tries).
+        *on_stop* is called after the last frame whenever capture stops (camera closed, reopened or lost).
+        Exceptions raised by the callbacks are printed, as there's no caller to raise them to.
+        Returns an id for remove_frame_callback.
+        """
+        return self._cam.add_frame_callback(callback, block, queue_size, on_stop)
+
+    def remove_frame_callback(self, callback_id: int):
+        """
+        Stop calling a callback added by add_frame_callback, after it has processed the frames already queued for it.
+        """
+        self._cam.remove_frame_callback(callback_id)
+
+    def frames(self, queue_size: int = 4) -> "FrameStream":
+        """
+        Async iterator over new frames, for use with asyncio: `async with cam.frames() as frames: async for frame in frames: ...`
+        Up to *queue_size* frames are kept while the consumer is busy, older ones are skipped.
+        Iteration ends when capture stops, raising the error which stopped it, if any.
+        Frames keep being delivered until the async with block is left (or aclose is called),
+        a stream which is just dropped stops once it's garbage collected.
+        Opens the camera if needed. Must be called from a coroutine, as frames are delivered to the running event loop.
+        """
+        if not self._initialized:
+            self.open()
+        return FrameStream(self, queue_size)
+
+    async def next_frame_async(self, timeout: Union[float, None] = None) -> Union["Frame", None]:
+        """
+        Wait for a frame newer than the last one returned by next_frame_async, without blocking the event loop.
+        Returns None if *timeout* seconds have passed or capture stopped (after raising its error, if any).
+        """
+        stream = self._async_stream
+        if stream is None or stream._stopped or stream._loop is not asyncio.get_running_loop():
+            # One stream serves all calls, replaced once capture stopped
+            if stream is not None:
+                await stream.aclose()
+            stream = self._async_stream = self.frames(queue_size=1)
+        try:
+            return await asyncio.wait_for(stream.__anext__(), timeout)
+        except (asyncio.TimeoutError, StopAsyncIteration):
+            return None
+
+    def stats(self) -> CaptureStats:
+        """
+        Measured frame rate and timings, counters of captured, dropped and failed frames.
+        Frames which failed to read or decode are skipped without raising anything,
+        last_error describes the most recent failure, see also check_err.
+        """
+        return self._cam.stats()
+
+    def check_err(self, frame_errors: bool = False):
+        """
+        Raise the error which stopped capture, or (once) the error of a failed format switch, if any.
+        With *frame_errors*, also raise (once) the most recent failure to read or decode a frame,
+        which is otherwise only counted by stats().
+        """
+        self._cam.check_err(frame_errors)
+
+    @property
+    def dropped_frames(self) -> int:
+        """
+        Number of frames evicted from a full queue before being read, since the camera was opened.
+        """
+        return self._cam.dropped_frames()
+
+    def start_recording(self, path):
+        """
+        Start writing captured frames to an MJPEG AVI file at *path*.
+        Frames are written from the capture thread, no need to poll them.
+        If the camera delivers MJPEG frames, they are stored as-is without re-encoding.
+        """
+        if not self._initialized:
+            self.open()
+        self._cam.start_recording(str(path))
+
+    def stop_recording(self):
+        """
+        Finish the recording started by start_recording.
+        Raises an error if anything went wrong while writing the file.
+        """
+        self._cam.stop_recording()
+
     def _info(self):
         return self._cam.info()
 
 
-def query(only_usable=True) -> list[CameraInfo]:
+FrameSet = omni_camera.FrameSet
+"""
+Frames of several cameras captured at (nearly) the same moment, in the order the cameras were passed to CameraGroup.
+Indexable like a list, frames gives all of them and skew the time (in seconds) between the first and the last capture.
+"""
+
+
+class CameraGroup:
+    """
+    Captures from several cameras at once (e.g. a stereo rig) and matches their frames by capture time.
+    Every camera captures on its own thread, frames captured within *tolerance* seconds of each other form a FrameSet.
+    Up to *history* recent frames of every camera are considered while looking for matches.
+    """
+
+    def __init__(self, cameras: List[Camera], tolerance: float = 0.01, history: int = 8):
+        self.cameras = list(cameras)
+        self._group = omni_camera.CameraGroup([cam._cam for cam in self.cameras], tolerance, history)
+        for cam in self.cameras:
+            if not cam._initialized:
+                cam.open()
+
+    def _check_err(self):
+        for cam in self.cameras:
+            cam._cam.check_err()
+
+    def poll_frameset(self) -> Union[FrameSet, None]:
+        """
+        Returns the newest set of matching frames not returned before, or None if there's none yet.
+        """
+        self._check_err()
+        return self._group.poll_frameset()
+
+    def wait_frameset(self, timeout: Union[float, None] = None) -> Union[FrameSet, None]:
+        """
+        Wait for a new set of matching frames.
+        Returns None if *timeout* seconds have passed or one of the cameras stopped capturing.
+        """
+        self._check_err()
+        frameset = self._group.wait_frameset(timeout)
+        self._check_err()
+        return frameset
+
+    def close(self):
+        """
+        Stop matching frames and close all cameras.
+        """
+        self._group.close()
+        for cam in self.cameras:
+            cam.close()
+
+    def __enter__(self) -> "CameraGroup":
+        return self
+
+    def __exit__(self, *_):
+        self.close()
+
+
+class FrameStream:
+    """
+    Async iterator over frames of a camera, see Camera.frames.
+    Frames are delivered by a frame callback which wakes up the event loop, nothing is polled.
+    Use as an async context manager (or call aclose) to stop receiving frames when done.
+    """
+
+    def __init__(self, camera: Camera, queue_size: int):
+        self._camera = camera
+        self._loop = asyncio.get_running_loop()
+        self._queue = asyncio.Queue()
+        self._queue_size = max(queue_size, 1)
+        self._stopped = False
+        # The callbacks only hold a weak reference, so that a stream nobody uses anymore gets collected
+        ref = weakref.ref(self)
+
+        def on_frame(frame: Frame):
+            stream = ref()
+            if stream is not None:
+                stream._notify(frame)
+
+        def on_stop():
+            stream = ref()
+            if stream is not None:
+                stream._notify(None)
+
+        self._callback_id = camera.add_frame_callback(on_frame, queue_size=self._queue_size, on_stop=on_stop)
+
+    def __del__(self):
+        callback_id, self._callback_id = getattr(self, "_callback_id", None), None
+        if callback_id is not None:
+            self._camera._cam.remove_frame_callback(callback_id)
+
+    def _notify(self, item):
+        try:
+            self._loop.call_soon_threadsafe(self._deliver, item)
+        except RuntimeError:
+            pass # Event loop is closed, nobody is waiting anymore
+
+    def _deliver(self, item):
+        if item is not None and self._queue.qsize() >= self._queue_size:
+            self._queue.get_nowait() # Skip the oldest frame
+        self._queue.put_nowait(item)
+
+    def __aiter__(self) -> "FrameStream":
+        return self
+
+    async def __anext__(self) -> Frame:
+        if self._stopped:
+            raise StopAsyncIteration
+        frame = await self._queue.get()
+        self._camera._cam.check_err()
+        if frame is None:
+            self._stopped = True
+            raise StopAsyncIteration
+        return frame
+
+    async def aclose(self):
+        """
+        Stop receiving frames.
+        """
+        if self._callback_id is not None:
+            callback_id, self._callback_id = self._callback_id, None
+            await self._loop.run_in_executor(None, self._camera.remove_frame_callback, callback_id)
+
+    async def __aenter__(self) -> "FrameStream":
+        return self
+
+    async def __aexit__(self, *_):
+        await self.aclose()
+
+
+@dataclass
+class DeviceEvent:
+    """
+    A camera was connected (*kind* is 'added') or disconnected ('removed'), see DeviceWatcher.
+    """
+    kind: str
+    camera: CameraInfo
+
+    @classmethod
+    def _from_event(cls, event) -> "DeviceEvent":
+        return cls(event.kind, CameraInfo._from_device(event.device))
+
+
+class DeviceWatcher:
+    """
+    Watches for cameras being connected and disconnected, on a thread of its own.
+    Changes are checked for every *interval* seconds, on Linux the backend is only queried when device nodes in /dev change.
+    Events can be polled (poll_event, wait_event), passed to callbacks (add_callback) or awaited (events).
+    *dev_dir* watches videoN entries of another directory instead of asking the backend, e.g. to test code without cameras.
+    """
+
+    def __init__(self, interval: float = 0.5, backend: str = "auto", test_patterns: bool = False,
+                 dev_dir: Union[str, Path, None] = None):
+        self._watcher = omni_camera.DeviceWatcher(interval, backend, test_patterns, dev_dir)
+
+    @property
+    def cameras(self) -> List[CameraInfo]:
+        """
+        Cameras connected as of the last check.
+        """
+        return list(map(CameraInfo._from_device, self._watcher.devices()))
+
+    def poll_event(self) -> Optional[DeviceEvent]:
+        """
+        Returns the oldest event not returned before, or None if there's none.
+        Only the last 64 events are kept, older ones are discarded if nobody polls them.
+        """
+        event = self._watcher.poll_event()
+        return None if event is None else DeviceEvent._from_event(event)
+
+    def wait_event(self, timeout: Union[float, None] = None) -> Optional[DeviceEvent]:
+        """
+        Like poll_event, but waits for an event if there's none.
+        Returns None if *timeout* seconds have passed or the watcher was closed.
+        """
+        event = self._watcher.wait_event(timeout)
+        return None if event is None else DeviceEvent._from_event(event)
+
+    def add_callback(self, callback: Callable[[DeviceEvent], Any], on_close: Optional[Callable[[], Any]] = None) -> int:
+        """
+        Call *callback* with every event, from the watcher thread. Events are still available to poll_event.
+        *on_close* is called once the watcher is closed. Returns an id for remove_callback.
+        """
+        return self._watcher.add_callback(lambda event: callback(DeviceEvent._from_event(event)), on_close)
+
+    def remove_callback(self, callback_id: int):
+        self._watcher.remove_callback(callback_id)
+
+    def events(self) -> "DeviceEventStream":
+        """
+        Async iterator over events, must be called from a running event loop.
+        Use it as an async context manager (or call aclose) to stop receiving events.
+        Iteration ends once the watcher is closed.
+        """
+        return DeviceEventStream(self)
+
+    def close(self):
+        """
+        Stop watching.
+        """
+        self._watcher.close()
+
+    def __enter__(self) -> "DeviceWatcher":
+        return self
+
+    def __exit__(self, *_):
+        self.close()
+
+
+class DeviceEventStream:
+    """
+    Async iterator over events of a DeviceWatcher, see DeviceWatcher.events.
+    """
+
+    def __init__(self, watcher: DeviceWatcher):
+        self._watcher = watcher
+        self._loop = asyncio.get_running_loop()
+        self._queue = asyncio.Queue()
+        self._stopped = False
+        self._callback_id = watcher.add_callback(self._notify, on_close=lambda: self._notify(None))
+        if watcher._watcher.is_closed():
+            self._notify(None) # Closed before the callback was added
+
+    def _notify(self, event: Optional[DeviceEvent]):
+        try:
+            self._loop.call_soon_threadsafe(self._queue.put_nowait, event)
+        except RuntimeError:
+            pass # Event loop is closed, nobody is waiting anymore
+
+    def __aiter__(self) -> "DeviceEventStream":
+        return self
+
+    async def __anext__(self) -> DeviceEvent:
+        if self._stopped:
+            raise StopAsyncIteration
+        event = await self._queue.get()
+        if event is None:
+            self._stopped = True
+            raise StopAsyncIteration
+        return event
+
+    async def aclose(self):
+        """
+        Stop receiving events.
+        """
+        if self._callback_id is not None:
+            callback_id, self._callback_id = self._callback_id, None
+            self._watcher.remove_callback(callback_id)
+
+    async def __aenter__(self) -> "DeviceEventStream":
+        return self
+
+    async def __aexit__(self, *_):
+        await self.aclose()
+
+
+def _frame_to_raw(frame: Union["Frame", None]) -> Union[tuple[int, int, bytes], None]:
+    if frame is None:
+        return None
+    return frame.width, frame.height, frame.data
+
+
+def _frame_to_np(frame: Union["Frame", None]) -> Union["np.ndarray", None]:
+    if frame is None:
+        return None
+    return np.asarray(frame) # Shares memory with the frame, no copy is made
+
+
+def _frame_to_pil(frame: Union["Frame", None]) -> Union["Image.Image", None]:
+    if frame is None:
+        return None
+    size = (frame.width, frame.height)
+    fmt = PixelFormat(frame.pixel_format)
+    if fmt is PixelFormat.RGB:
+        return Image.frombytes("RGB", size, frame.data)
+    if fmt is PixelFormat.BGR:
+        return Image.frombytes("RGB", size, frame.data, "raw", "BGR")
+    if fmt is PixelFormat.RGBA:
+        return Image.frombytes("RGBA", size, frame.data)
+    if fmt is PixelFormat.LUMA:
+        return Image.frombytes("L", size, frame.data)
+    raise ValueError(f"Can't convert {fmt.value} frames to pillow images")
+
+
+def backends() -> List[str]:
+    """
+    Names of the backends available in this build: the native one of the platform
+    ('v4l2', 'msmf' or 'avfoundation') and 'test', which provides virtual test pattern cameras.
+    "auto" can be passed wherever a backend is expected to use the native one.
+    """
+    return omni_camera.backends()
+
+
+def find_camera(stable_id: str, backend: str = "auto") -> Optional[CameraInfo]:
+    """
+    Find a connected camera by CameraInfo.stable_id, e.g. one saved in a configuration file.
+    Returns None if it isn't connected.
+    """
+    device = omni_camera.find_device(stable_id, backend)
+    return None if device is None else CameraInfo._from_device(device)
+
+
+def query(only_usable=True, test_patterns=False, backend: str = "auto") -> list[CameraInfo]:
     """
-    Returns a list of CameraInfo objects, one for every available camera.
+    Returns a list of CameraInfo objects, one for every available camera of *backend* (see backends()).
+    If *test_patterns* is true, virtual cameras generating test patterns are listed as well,
+    they can be used to test code on machines without any cameras attached.
     """
-    result = map(lambda x: CameraInfo(*x), omni_camera.query())
+    result = map(CameraInfo._from_device, omni_camera.query(test_patterns, backend))
     if only_usable:
         result = filter(CameraInfo.can_open, result)
     return list(result)
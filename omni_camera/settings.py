@@ -0,0 +1,161 @@
+"""
+Saving and restoring camera settings (selected format and control values).
+"""
+import json
+import math
+from pathlib import Path
+from typing import Any, Dict, Union
+
+SETTINGS_VERSION = 1
+
+# Kinds of controls whose values are tuples, stored as lists.
+_TUPLE_KINDS = ("key_value", "point", "rgb")
+# Kinds of controls applied before the others, automatic mode switches are among them.
+_MODE_KINDS = ("boolean", "enum")
+
+
+def _encode_value(kind: str, value: Any) -> Any:
+    if kind == "bytes" and value is not None:
+        return value.hex()
+    if kind in _TUPLE_KINDS and value is not None:
+        return list(value)
+    return value
+
+
+def _decode_value(kind: str, value: Any) -> Any:
+    if kind == "bytes" and isinstance(value, str):
+        return bytes.fromhex(value)
+    if kind in _TUPLE_KINDS and isinstance(value, list):
+        return tuple(value)
+    return value
+
+
+def export_settings(camera) -> Dict[str, Any]:
+    """
+    Collect the selected format and the value of every control of *camera*.
+    """
+    fmt = camera.get_format()
+    controls = {}
+    for name, control in camera.get_controls().items():
+        try:
+            controls[name] = {
+                "kind": control.kind,
+                "value": _encode_value(control.kind, control.value),
+                "active": control.is_active,
+            }
+        except Exception:
+            continue  # Some cameras report controls which can't be read
+    return {
+        "version": SETTINGS_VERSION,
+        "format": {
+            "width": fmt.width,
+            "height": fmt.height,
+            "frame_rate": fmt.frame_rate,
+            "frame_format": fmt.frame_format.value,
+        },
+        "controls": controls,
+    }
+
+
+def apply_settings(camera, settings: Dict[str, Any]) -> Dict[str, Exception]:
+    """
+    Apply settings produced by export_settings to *camera*.
+    Returns a dictionary of errors, keyed by control name (or "format"), empty if everything was applied.
+    Boolean and enum controls are applied first, as they include mode switches like 'Exposure, Auto'
+    which decide whether other controls are active. Controls saved as inactive are skipped,
+    their value follows their automatic counterpart.
+    """
+    version = settings.get("version")
+    if version != SETTINGS_VERSION:
+        raise ValueError(f"Unsupported settings version {version!r}")
+    errors = {}
+    fmt = settings.get("format")
+    if fmt is not None:
+        try:
+            options = camera.get_format_options()
+            matching = [x for x in options if x.width == fmt["width"] and x.height == fmt["height"]
+                        and x.frame_rate == fmt["frame_rate"] and x.frame_format.value == fmt["frame_format"]]
+            if not matching:
+                raise ValueError(f"Format {fmt} is not supported by this camera")
+            camera.set_format(matching[0], wait=True)
+        except Exception as e:
+            errors["format"] = e
+    controls = camera.get_controls()
+    saved_controls = settings.get("controls", {}).items()
+    # sorted is stable, both passes keep the saved order
+    for name, saved in sorted(saved_controls, key=lambda item: item[1].get("kind") not in _MODE_KINDS):
+        try:
+            control = controls.get(name)
+            if control is None:
+                raise KeyError(f"Camera has no control named {name!r}")
+            if "read_only" in control.flags or not saved.get("active", True):
+                continue
+            control.set_value(_decode_value(control.kind, saved["value"]))
+        except Exception as e:
+            errors[name] = e
+    return errors
+
+
+def _toml_key(key: str) -> str:
+    if key and all(c.isalnum() or c in "-_" for c in key):
+        return key
+    return json.dumps(key)
+
+
+def _toml_value(value: Any) -> str:
+    if isinstance(value, bool):
+        return "true" if value else "false"
+    if isinstance(value, int):
+        return str(value)
+    if isinstance(value, float):
+        if math.isnan(value):
+            return "nan"
+        return repr(value)  # "inf" and "-inf" are valid TOML as well
+    if isinstance(value, str):
+        return json.dumps(value)
+    if isinstance(value, (list, tuple)):
+        return "[" + ", ".join(map(_toml_value, value)) + "]"
+    raise TypeError(f"Can't store {value!r} in TOML")
+
+
+def _to_toml(settings: Dict[str, Any]) -> str:
+    lines = [f"version = {_toml_value(settings['version'])}"]
+    tables = [("format", settings.get("format"))]
+    tables += [(f"controls.{_toml_key(name)}", control) for name, control in settings.get("controls", {}).items()]
+    for name, table in tables:
+        if table is None:
+            continue
+        lines += ["", f"[{name}]"]
+        # TOML has no null, missing values are read back as None
+        lines += [f"{_toml_key(k)} = {_toml_value(v)}" for k, v in table.items() if v is not None]
+    return "\n".join(lines) + "\n"
+
+
+def save_settings(settings: Dict[str, Any], path: Union[str, Path]):
+    """
+    Write settings to *path*, as TOML if it ends with .toml and as JSON otherwise.
+    """
+    path = Path(path)
+    if path.suffix == ".toml":
+        text = _to_toml(settings)
+    else:
+        text = json.dumps(settings, indent=2)
+    path.write_text(text, encoding="utf-8")
+
+
+def load_settings(path: Union[str, Path]) -> Dict[str, Any]:
+    """
+    Read settings written by save_settings. Reading TOML requires Python 3.11+ or the tomli package.
+    """
+    path = Path(path)
+    text = path.read_text(encoding="utf-8")
+    if path.suffix == ".toml":
+        try:
+            import tomllib
+        except ImportError:
+            import tomli as tomllib
+        settings = tomllib.loads(text)
+        for control in settings.get("controls", {}).values():
+            control.setdefault("value", None)
+        return settings
+    return json.loads(text)
@@ -0,0 +1,51 @@
+use nokhwa::utils::ApiBackend;
+
+/// Where cameras come from: one of the capture APIs nokhwa supports, or the built-in test patterns.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Backend {
+    Native(ApiBackend),
+    Test,
+}
+
+impl Backend {
+    pub(crate) const NAMES: &'static str = "'auto', 'v4l2', 'msmf', 'avfoundation', 'test'";
+
+    pub(crate) fn from_name(name: &str) -> Option<Backend> {
+        match name {
+            "auto" => Some(Backend::Native(ApiBackend::Auto)),
+            "v4l2" => Some(Backend::Native(ApiBackend::Video4Linux)),
+            "msmf" => Some(Backend::Native(ApiBackend::MediaFoundation)),
+            "avfoundation" => Some(Backend::Native(ApiBackend::AVFoundation)),
+            "test" => Some(Backend::Test),
+            _ => None,
+        }
+    }
+    pub(crate) fn name(self) -> &'static str {
+        match self.resolved() {
+            Backend::Native(ApiBackend::Video4Linux) => "v4l2",
+            Backend::Native(ApiBackend::MediaFoundation) => "msmf",
+            Backend::Native(ApiBackend::AVFoundation) => "avfoundation",
+            Backend::Native(_) => "auto",
+            Backend::Test => "test",
+        }
+    }
+    /// The backend nokhwa picks for Auto on this platform.
+    pub(crate) fn resolved(self) -> Backend {
+        match self {
+            Backend::Native(ApiBackend::Auto) => {
+                nokhwa::native_api_backend().map_or(self, Backend::Native)
+            }
+            other => other,
+        }
+    }
+    /// Backends compiled into this build, the native one of the platform and the test patterns.
+    pub(crate) fn available() -> Vec<Backend> {
+        let native = Backend::Native(ApiBackend::Auto).resolved();
+        let mut backends = Vec::new();
+        if native != Backend::Native(ApiBackend::Auto) {
+            backends.push(native);
+        }
+        backends.push(Backend::Test);
+        backends
+    }
+}
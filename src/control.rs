@@ -0,0 +1,308 @@
+use std::sync::{Mutex, MutexGuard, Weak};
+
+use nokhwa::utils::{
+    CameraControl, ControlValueDescription, ControlValueSetter, KnownCameraControlFlag,
+};
+use parking_lot::FairMutex;
+use pyo3::{exceptions::PyValueError, prelude::*, types::PyBytes, IntoPyObjectExt};
+
+use crate::{
+    errors::{self, ControlError},
+    source::FrameSource,
+};
+
+/// Labels of menu items for well-known menu controls, nokhwa only reports item values.
+const MENU_LABELS: &[(&str, &[(i64, &str)])] = &[
+    (
+        "power line frequency",
+        &[(0, "Disabled"), (1, "50 Hz"), (2, "60 Hz"), (3, "Auto")],
+    ),
+    (
+        "auto exposure",
+        &[
+            (0, "Auto Mode"),
+            (1, "Manual Mode"),
+            (2, "Shutter Priority Mode"),
+            (3, "Aperture Priority Mode"),
+        ],
+    ),
+    (
+        "exposure, auto",
+        &[
+            (0, "Auto Mode"),
+            (1, "Manual Mode"),
+            (2, "Shutter Priority Mode"),
+            (3, "Aperture Priority Mode"),
+        ],
+    ),
+];
+
+fn menu_label(control_name: &str, value: i64) -> String {
+    let control_name = control_name.to_lowercase();
+    MENU_LABELS
+        .iter()
+        .find(|(name, _)| *name == control_name)
+        .and_then(|(_, labels)| labels.iter().find(|(item, _)| *item == value))
+        .map_or_else(|| value.to_string(), |(_, label)| label.to_string())
+}
+
+fn kind_name(description: &ControlValueDescription) -> &'static str {
+    match description {
+        ControlValueDescription::None => "none",
+        ControlValueDescription::Integer { .. } => "integer",
+        ControlValueDescription::IntegerRange { .. } => "integer_range",
+        ControlValueDescription::Float { .. } => "float",
+        ControlValueDescription::FloatRange { .. } => "float_range",
+        ControlValueDescription::Boolean { .. } => "boolean",
+        ControlValueDescription::String { .. } => "string",
+        ControlValueDescription::Bytes { .. } => "bytes",
+        ControlValueDescription::KeyValuePair { .. } => "key_value",
+        ControlValueDescription::Point { .. } => "point",
+        ControlValueDescription::Enum { .. } => "enum",
+        ControlValueDescription::RGB { .. } => "rgb",
+    }
+}
+
+fn flag_name(flag: &KnownCameraControlFlag) -> &'static str {
+    match flag {
+        KnownCameraControlFlag::Automatic => "automatic",
+        KnownCameraControlFlag::Manual => "manual",
+        KnownCameraControlFlag::Continuous => "continuous",
+        KnownCameraControlFlag::ReadOnly => "read_only",
+        KnownCameraControlFlag::WriteOnly => "write_only",
+        KnownCameraControlFlag::Volatile => "volatile",
+        KnownCameraControlFlag::Disabled => "disabled",
+    }
+}
+
+fn pick<T>(default: bool, value: T, default_value: T) -> T {
+    if default {
+        default_value
+    } else {
+        value
+    }
+}
+
+/// Converts the current (or default) value of a control to a python object.
+fn description_value(
+    py: Python<'_>,
+    description: &ControlValueDescription,
+    default: bool,
+) -> PyResult<PyObject> {
+    match description {
+        ControlValueDescription::None => Ok(py.None()),
+        ControlValueDescription::Integer {
+            value,
+            default: default_value,
+            ..
+        }
+        | ControlValueDescription::IntegerRange {
+            value,
+            default: default_value,
+            ..
+        }
+        | ControlValueDescription::Enum {
+            value,
+            default: default_value,
+            ..
+        } => pick(default, *value, *default_value).into_py_any(py),
+        ControlValueDescription::Float {
+            value,
+            default: default_value,
+            ..
+        }
+        | ControlValueDescription::FloatRange {
+            value,
+            default: default_value,
+            ..
+        } => pick(default, *value, *default_value).into_py_any(py),
+        ControlValueDescription::Boolean {
+            value,
+            default: default_value,
+        } => pick(default, *value, *default_value).into_py_any(py),
+        ControlValueDescription::String {
+            value,
+            default: default_value,
+        } => pick(default, Some(value.clone()), default_value.clone()).into_py_any(py),
+        ControlValueDescription::Bytes {
+            value,
+            default: default_value,
+        } => Ok(PyBytes::new(py, pick(default, value, default_value))
+            .into_any()
+            .unbind()),
+        ControlValueDescription::KeyValuePair {
+            key,
+            value,
+            default: default_value,
+        } => pick(default, (*key, *value), *default_value).into_py_any(py),
+        ControlValueDescription::Point {
+            value,
+            default: default_value,
+        } => pick(default, *value, *default_value).into_py_any(py),
+        ControlValueDescription::RGB {
+            value,
+            default: default_value,
+            ..
+        } => pick(default, *value, *default_value).into_py_any(py),
+    }
+}
+
+#[pyclass]
+pub(crate) struct CamControl {
+    pub(crate) cam: Weak<FairMutex<Box<dyn FrameSource>>>,
+    pub(crate) control: Mutex<CameraControl>,
+}
+
+impl CamControl {
+    /// Reads the control from the device again, so that its value and flags are up to date.
+    fn refreshed(&self) -> PyResult<MutexGuard<'_, CameraControl>> {
+        let cam = self.cam.upgrade().ok_or_else(|| {
+            ControlError::new_err("Control is unusable as camera object has been dropped")
+        })?;
+        let mut control = self.control.lock().unwrap();
+        let fresh = cam.lock().camera_control(control.control());
+        match fresh {
+            Ok(fresh) => {
+                *control = fresh;
+                Ok(control)
+            }
+            Err(error) => Err(errors::to_py(&error)),
+        }
+    }
+    /// Converts a python value to a setter matching the type of this control.
+    fn setter(&self, value: &Bound<'_, PyAny>) -> PyResult<ControlValueSetter> {
+        let control = self.control.lock().unwrap();
+        Ok(match control.description() {
+            ControlValueDescription::Integer { .. }
+            | ControlValueDescription::IntegerRange { .. } => {
+                ControlValueSetter::Integer(value.extract()?)
+            }
+            ControlValueDescription::Float { .. } | ControlValueDescription::FloatRange { .. } => {
+                ControlValueSetter::Float(value.extract()?)
+            }
+            ControlValueDescription::Boolean { .. } => {
+                ControlValueSetter::Boolean(value.extract()?)
+            }
+            ControlValueDescription::String { .. } => ControlValueSetter::String(value.extract()?),
+            ControlValueDescription::Bytes { .. } => ControlValueSetter::Bytes(value.extract()?),
+            ControlValueDescription::KeyValuePair { .. } => {
+                let (key, value) = value.extract()?;
+                ControlValueSetter::KeyValue(key, value)
+            }
+            ControlValueDescription::Point { .. } => {
+                let (x, y) = value.extract()?;
+                ControlValueSetter::Point(x, y)
+            }
+            ControlValueDescription::Enum { possible, .. } => {
+                // Menu items can be chosen either by value or by label.
+                let item = match value.extract::<String>() {
+                    Ok(label) => possible
+                        .iter()
+                        .copied()
+                        .find(|item| menu_label(control.name(), *item) == label)
+                        .ok_or_else(|| {
+                            PyValueError::new_err(format!("Unknown menu item {label:?}"))
+                        })?,
+                    Err(_) => value.extract()?,
+                };
+                ControlValueSetter::EnumValue(item)
+            }
+            ControlValueDescription::RGB { .. } => {
+                let (r, g, b) = value.extract()?;
+                ControlValueSetter::RGB(r, g, b)
+            }
+            ControlValueDescription::None => {
+                return Err(PyValueError::new_err("Control doesn't accept values"))
+            }
+        })
+    }
+}
+
+#[pymethods]
+impl CamControl {
+    /// Type of values this control accepts, one of 'integer', 'integer_range', 'float', 'float_range',
+    /// 'boolean', 'string', 'bytes', 'key_value', 'point', 'enum', 'rgb', 'none'.
+    fn kind(&self) -> &'static str {
+        kind_name(self.control.lock().unwrap().description())
+    }
+    /// Current value, read from the device. Its type depends on kind(), see set_value.
+    fn value(&self, py: Python<'_>) -> PyResult<PyObject> {
+        description_value(py, self.refreshed()?.description(), false)
+    }
+    /// Default value, of the same type as value().
+    fn default(&self, py: Python<'_>) -> PyResult<PyObject> {
+        description_value(py, self.refreshed()?.description(), true)
+    }
+    /// Whether the control is active, read from the device.
+    fn is_active(&self) -> PyResult<bool> {
+        Ok(self.refreshed()?.active())
+    }
+    /// Flags reported by the device, any of 'automatic', 'manual', 'continuous', 'read_only',
+    /// 'write_only', 'volatile', 'disabled'.
+    #[getter]
+    fn flags(&self) -> PyResult<Vec<&'static str>> {
+        Ok(self.refreshed()?.flag().iter().map(flag_name).collect())
+    }
+    /// (min, max, step) of an 'integer_range' control.
+    fn value_range(&self) -> PyResult<(i64, i64, i64)> {
+        let control = self.control.lock().unwrap();
+        let control_desc = control.description();
+        match control_desc {
+            ControlValueDescription::IntegerRange { min, max, step, .. } => Ok((*min, *max, *step)),
+            other => Err(PyValueError::new_err(format!(
+                "Control of kind '{}' has no integer range",
+                kind_name(other)
+            ))),
+        }
+    }
+    /// (min, max, step) of a 'float_range' control.
+    fn float_range(&self) -> PyResult<(f64, f64, f64)> {
+        let control = self.control.lock().unwrap();
+        match control.description() {
+            ControlValueDescription::FloatRange { min, max, step, .. } => Ok((*min, *max, *step)),
+            other => Err(PyValueError::new_err(format!(
+                "Control of kind '{}' has no float range",
+                kind_name(other)
+            ))),
+        }
+    }
+    /// (value, label) pairs of an 'enum' control.
+    /// Labels are only known for some common controls, otherwise the value is used as label.
+    fn menu_items(&self) -> PyResult<Vec<(i64, String)>> {
+        let control = self.control.lock().unwrap();
+        match control.description() {
+            ControlValueDescription::Enum { possible, .. } => Ok(possible
+                .iter()
+                .map(|item| (*item, menu_label(control.name(), *item)))
+                .collect()),
+            other => Err(PyValueError::new_err(format!(
+                "Control of kind '{}' has no menu items",
+                kind_name(other)
+            ))),
+        }
+    }
+    /// Set a value of a type matching kind(): int, float, bool, str, bytes, (key, value) tuple,
+    /// (x, y) tuple, menu item value or label, (r, g, b) tuple.
+    /// None would switch the control to auto mode, which backends don't support for individual controls,
+    /// so it raises ControlError. Cameras usually have separate controls for that, e.g. 'Exposure, Auto'.
+    #[pyo3(signature = (value=None))]
+    fn set_value(&self, value: Option<Bound<'_, PyAny>>) -> PyResult<()> {
+        let Some(value) = value else {
+            return Err(ControlError::new_err(format!(
+                "Control {:?} can't be switched to auto mode, set its automatic counterpart instead",
+                self.control.lock().unwrap().name()
+            )));
+        };
+        let setter = self.setter(&value)?;
+        let control = self.control.lock().unwrap();
+        match self.cam.upgrade() {
+            Some(cam) => match cam.lock().set_camera_control(control.control(), setter) {
+                Ok(_) => Ok(()),
+                Err(error) => Err(errors::to_py(&error)),
+            },
+            None => Err(ControlError::new_err(
+                "Control is unusable as camera object has been dropped".to_string(),
+            )),
+        }
+    }
+}
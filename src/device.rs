@@ -0,0 +1,271 @@
+use std::path::Path;
+
+use nokhwa::{
+    utils::{ApiBackend, CameraIndex, CameraInfo},
+    NokhwaError,
+};
+use pyo3::prelude::*;
+
+use crate::{backend::Backend, test_pattern::TestPattern};
+
+/// Describes a camera, including the details needed to find it again after it's been replugged.
+#[derive(Clone)]
+#[pyclass]
+pub struct DeviceInfo {
+    /// Index to open the camera with, None if the backend identifies the camera by a string.
+    #[pyo3(get)]
+    index: Option<u32>,
+    /// Index as reported by the backend, either a number or a backend specific string.
+    #[pyo3(get)]
+    id: String,
+    #[pyo3(get)]
+    name: String,
+    #[pyo3(get)]
+    description: String,
+    #[pyo3(get)]
+    misc: String,
+    /// Backend which listed the camera, also used to open it.
+    pub(crate) backend: Backend,
+    /// Device node, e.g. /dev/video0.
+    #[pyo3(get)]
+    pub(crate) path: Option<String>,
+    #[pyo3(get)]
+    vendor_id: Option<u16>,
+    #[pyo3(get)]
+    product_id: Option<u16>,
+    #[pyo3(get)]
+    serial: Option<String>,
+    /// Location on the USB bus (e.g. 1-2.3), stays the same as long as the camera is plugged into the same port.
+    #[pyo3(get)]
+    bus: Option<String>,
+    /// Index of the device node among the nodes of the same device (V4L2 devices may have several).
+    node: u32,
+    pub(crate) camera_index: CameraIndex,
+}
+
+impl DeviceInfo {
+    pub(crate) fn from_camera_info(info: &CameraInfo, backend: Backend) -> DeviceInfo {
+        let camera_index = info.index().clone();
+        let mut device = DeviceInfo {
+            index: match camera_index {
+                CameraIndex::Index(index) => Some(index),
+                CameraIndex::String(_) => None,
+            },
+            id: camera_index.as_string(),
+            name: info.human_name(),
+            description: info.description().to_owned(),
+            misc: info.misc(),
+            backend: backend.resolved(),
+            path: None,
+            vendor_id: None,
+            product_id: None,
+            serial: None,
+            bus: None,
+            node: 0,
+            camera_index,
+        };
+        if let Some(index) = device.index {
+            device.read_sysfs(index);
+        }
+        device
+    }
+
+    fn test_pattern(index: u32, name: &str) -> DeviceInfo {
+        DeviceInfo {
+            index: Some(index),
+            id: index.to_string(),
+            name: name.to_string(),
+            description: "OmniCamera virtual test pattern".to_string(),
+            misc: String::new(),
+            backend: Backend::Test,
+            path: None,
+            vendor_id: None,
+            product_id: None,
+            serial: None,
+            bus: None,
+            node: 0,
+            camera_index: CameraIndex::Index(index),
+        }
+    }
+
+    /// Describes a bare device node, e.g. one in a fake device directory used for testing the watcher.
+    pub(crate) fn from_node(path: &Path, index: u32) -> DeviceInfo {
+        let name = path
+            .file_name()
+            .map_or(String::new(), |name| name.to_string_lossy().into_owned());
+        DeviceInfo {
+            index: Some(index),
+            id: index.to_string(),
+            description: format!("Device node {}", path.display()),
+            name,
+            misc: String::new(),
+            backend: Backend::Native(ApiBackend::Auto).resolved(),
+            path: Some(path.display().to_string()),
+            vendor_id: None,
+            product_id: None,
+            serial: None,
+            bus: None,
+            node: 0,
+            camera_index: CameraIndex::Index(index),
+        }
+    }
+
+    /// Fills in USB details from sysfs, the V4L2 backend doesn't report them.
+    #[cfg(target_os = "linux")]
+    fn read_sysfs(&mut self, index: u32) {
+        use std::fs;
+
+        let read = |path: &Path| {
+            fs::read_to_string(path)
+                .ok()
+                .map(|text| text.trim().to_string())
+                .filter(|text| !text.is_empty())
+        };
+        let read_hex =
+            |path: &Path| read(path).and_then(|text| u16::from_str_radix(&text, 16).ok());
+        self.path = Some(format!("/dev/video{index}"));
+        let node = Path::new("/sys/class/video4linux").join(format!("video{index}"));
+        self.node = read(&node.join("index"))
+            .and_then(|text| text.parse().ok())
+            .unwrap_or(0);
+        let Ok(interface) = fs::canonicalize(node.join("device")) else {
+            return;
+        };
+        let Some(usb) = interface
+            .ancestors()
+            .find(|dir| dir.join("idVendor").exists())
+        else {
+            return;
+        };
+        self.vendor_id = read_hex(&usb.join("idVendor"));
+        self.product_id = read_hex(&usb.join("idProduct"));
+        self.serial = read(&usb.join("serial"));
+        self.bus = usb
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned());
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn read_sysfs(&mut self, _index: u32) {}
+}
+
+#[pymethods]
+impl DeviceInfo {
+    /// Name of the backend which listed the camera, see backends().
+    #[getter(backend)]
+    fn backend_name(&self) -> &'static str {
+        self.backend.name()
+    }
+    /// Identifies the camera regardless of the index it got, e.g. usb:046d:0809:SERIAL.
+    /// Cameras without a serial number are identified by the USB port they're plugged into,
+    /// and if USB details aren't available, by what the backend reports about them.
+    #[getter]
+    pub(crate) fn stable_id(&self) -> String {
+        let mut id = match (self.vendor_id, self.product_id) {
+            (Some(vendor), Some(product)) => match (&self.serial, &self.bus) {
+                (Some(serial), _) => format!("usb:{vendor:04x}:{product:04x}:{serial}"),
+                (None, Some(bus)) => format!("usb:{vendor:04x}:{product:04x}@{bus}"),
+                (None, None) => format!("usb:{vendor:04x}:{product:04x}"),
+            },
+            _ if self.backend == Backend::Test => format!("test:{}", self.id),
+            _ => format!("{}:{}:{}", self.backend.name(), self.name, self.misc),
+        };
+        if self.node != 0 {
+            id += &format!("#{}", self.node);
+        }
+        id
+    }
+    fn __repr__(&self) -> String {
+        format!(
+            "DeviceInfo(id={:?}, name={:?}, stable_id={:?})",
+            self.id,
+            self.name,
+            self.stable_id()
+        )
+    }
+}
+
+/// Lists cameras of *backend*, optionally followed by the virtual test patterns.
+pub(crate) fn query_devices(
+    test_patterns: bool,
+    backend: Backend,
+) -> Result<Vec<DeviceInfo>, NokhwaError> {
+    let mut devices: Vec<DeviceInfo> = match backend {
+        Backend::Native(api) => nokhwa::query(api)?
+            .iter()
+            .map(|info| DeviceInfo::from_camera_info(info, backend))
+            .collect(),
+        Backend::Test => Vec::new(),
+    };
+    if test_patterns || backend == Backend::Test {
+        devices.extend(
+            TestPattern::devices().map(|(index, name)| DeviceInfo::test_pattern(index, name)),
+        );
+    }
+    Ok(devices)
+}
+
+/// Finds a connected camera of *backend* by DeviceInfo.stable_id, preferring one at *index* if several match.
+pub(crate) fn find_device(
+    stable_id: &str,
+    index: Option<&CameraIndex>,
+    backend: Backend,
+) -> Result<DeviceInfo, NokhwaError> {
+    let backend = if stable_id.starts_with("test:") {
+        Backend::Test
+    } else {
+        backend
+    };
+    query_devices(false, backend)?
+        .into_iter()
+        .filter(|device| device.stable_id() == stable_id)
+        .max_by_key(|device| Some(&device.camera_index) == index)
+        .ok_or_else(|| {
+            NokhwaError::OpenDeviceError(stable_id.to_string(), "No such device".to_string())
+        })
+}
+
+/// Device number of a V4L2 device node, following symlinks such as /dev/v4l/by-id/*.
+#[cfg(target_os = "linux")]
+fn video_node_index(path: &str) -> Option<u32> {
+    let path = std::fs::canonicalize(path).ok()?;
+    path.to_str()?.strip_prefix("/dev/video")?.parse().ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn video_node_index(_path: &str) -> Option<u32> {
+    None
+}
+
+/// Resolves a camera of *backend* given as a string, which can be a device path, a stable id,
+/// an index, a backend specific identifier or a camera name, in that order.
+/// Strings which don't match any known camera are passed on to the backend as they are.
+pub(crate) fn resolve_name(name: &str, backend: Backend) -> Result<CameraIndex, NokhwaError> {
+    if let Some(index) = video_node_index(name) {
+        return Ok(CameraIndex::Index(index));
+    }
+    if name.starts_with("usb:") || name.starts_with("test:") {
+        return find_device(name, None, backend).map(|device| device.camera_index);
+    }
+    if let Ok(index) = name.parse() {
+        return Ok(CameraIndex::Index(index));
+    }
+    // Backend specific identifiers may work even if listing cameras doesn't
+    let devices = query_devices(false, backend).unwrap_or_default();
+    if let Some(device) = devices
+        .iter()
+        .find(|device| device.id == name || device.stable_id() == name)
+    {
+        return Ok(device.camera_index.clone());
+    }
+    let mut by_name = devices.iter().filter(|device| device.name == name);
+    if let Some(device) = by_name.next() {
+        if by_name.next().is_some() {
+            return Err(NokhwaError::GeneralError(format!(
+                "Several cameras are named {name:?}, open one by path or stable id instead"
+            )));
+        }
+        return Ok(device.camera_index.clone());
+    }
+    Ok(CameraIndex::String(name.to_string()))
+}
@@ -0,0 +1,191 @@
+use nokhwa::NokhwaError;
+use pyo3::{create_exception, exceptions::PyRuntimeError, prelude::*};
+
+create_exception!(
+    omni_camera,
+    CameraError,
+    PyRuntimeError,
+    "Base class of all errors raised by cameras."
+);
+create_exception!(
+    omni_camera,
+    DeviceNotFoundError,
+    CameraError,
+    "The camera doesn't exist or has been unplugged."
+);
+create_exception!(
+    omni_camera,
+    PermissionDeniedError,
+    CameraError,
+    "Not allowed to access the camera."
+);
+create_exception!(
+    omni_camera,
+    DeviceBusyError,
+    CameraError,
+    "The camera is in use by another process."
+);
+create_exception!(
+    omni_camera,
+    UnsupportedFormatError,
+    CameraError,
+    "The camera can't capture in the requested format."
+);
+create_exception!(
+    omni_camera,
+    StreamClosedError,
+    CameraError,
+    "The camera isn't streaming, or the stream broke down."
+);
+create_exception!(
+    omni_camera,
+    ControlError,
+    CameraError,
+    "Reading or setting a camera control failed."
+);
+
+/// Extracts N from the "(os error N)" that std::io::Error appends to its message.
+fn os_error_code(message: &str) -> Option<i32> {
+    let start = message.rfind("(os error ")? + "(os error ".len();
+    let (code, _) = message[start..].split_once(')')?;
+    code.parse().ok()
+}
+
+/// Backends only report some failures as text, so the OS error is recognised by its message.
+fn os_error(message: &str) -> Option<PyErr> {
+    let lower = message.to_lowercase();
+    let has = |needles: &[&str]| needles.iter().any(|needle| lower.contains(needle));
+    let code = os_error_code(message);
+    if code == Some(13) || has(&["permission denied", "access denied", "access is denied"]) {
+        Some(PermissionDeniedError::new_err(message.to_string()))
+    } else if code == Some(16) || has(&["busy", "in use"]) {
+        Some(DeviceBusyError::new_err(message.to_string()))
+    } else if matches!(code, Some(2 | 19)) || has(&["no such file", "no such device", "not found"])
+    {
+        Some(DeviceNotFoundError::new_err(message.to_string()))
+    } else {
+        None
+    }
+}
+
+fn is_format_property(property: &str) -> bool {
+    ["Format", "Resolution", "FrameRate"]
+        .iter()
+        .any(|name| property.contains(name))
+}
+
+/// Converts a nokhwa error to the matching python exception.
+pub(crate) fn to_py(error: &NokhwaError) -> PyErr {
+    let message = error.to_string();
+    match error {
+        NokhwaError::OpenDeviceError(_, _) => {
+            os_error(&message).unwrap_or_else(|| DeviceNotFoundError::new_err(message))
+        }
+        NokhwaError::OpenStreamError(_)
+        | NokhwaError::ReadFrameError(_)
+        | NokhwaError::StreamShutdownError(_) => {
+            os_error(&message).unwrap_or_else(|| StreamClosedError::new_err(message))
+        }
+        // Backends name the property differently, e.g. "CameraFormat" or "Resolution, FrameFormat"
+        NokhwaError::SetPropertyError { property, .. } if is_format_property(property) => {
+            UnsupportedFormatError::new_err(message)
+        }
+        NokhwaError::SetPropertyError { .. } | NokhwaError::GetPropertyError { .. } => {
+            ControlError::new_err(message)
+        }
+        _ => CameraError::new_err(message),
+    }
+}
+
+pub(crate) fn register(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add("CameraError", py.get_type::<CameraError>())?;
+    m.add("DeviceNotFoundError", py.get_type::<DeviceNotFoundError>())?;
+    m.add(
+        "PermissionDeniedError",
+        py.get_type::<PermissionDeniedError>(),
+    )?;
+    m.add("DeviceBusyError", py.get_type::<DeviceBusyError>())?;
+    m.add(
+        "UnsupportedFormatError",
+        py.get_type::<UnsupportedFormatError>(),
+    )?;
+    m.add("StreamClosedError", py.get_type::<StreamClosedError>())?;
+    m.add("ControlError", py.get_type::<ControlError>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::PyTypeInfo;
+
+    use super::*;
+
+    fn open_error(error: &str) -> NokhwaError {
+        NokhwaError::OpenDeviceError("0".to_string(), error.to_string())
+    }
+
+    fn raises<T: PyTypeInfo>(error: &NokhwaError) -> bool {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| to_py(error).is_instance_of::<T>(py))
+    }
+
+    #[test]
+    fn parses_os_error_code() {
+        assert_eq!(os_error_code("Permission denied (os error 13)"), Some(13));
+        assert_eq!(os_error_code("(os error 2) then (os error 22)"), Some(22));
+        assert_eq!(os_error_code("Invalid argument (os error 22"), None);
+        assert_eq!(os_error_code("no os error"), None);
+    }
+
+    #[test]
+    fn maps_os_errors_by_exact_code() {
+        assert!(raises::<PermissionDeniedError>(&open_error(
+            "(os error 13)"
+        )));
+        assert!(raises::<DeviceBusyError>(&open_error("(os error 16)")));
+        assert!(raises::<DeviceNotFoundError>(&open_error("(os error 2)")));
+        assert!(raises::<DeviceNotFoundError>(&open_error("(os error 19)")));
+        let stream_error = |code| NokhwaError::ReadFrameError(format!("(os error {code})"));
+        // Codes sharing a prefix with the ones above aren't mistaken for them
+        for code in [22, 24, 130, 160] {
+            let error = stream_error(code);
+            assert!(raises::<StreamClosedError>(&error), "{error}");
+        }
+    }
+
+    #[test]
+    fn maps_os_errors_by_message() {
+        let error = open_error("Device or resource busy");
+        assert!(raises::<DeviceBusyError>(&error));
+        let error = NokhwaError::OpenStreamError("Access is denied.".to_string());
+        assert!(raises::<PermissionDeniedError>(&error));
+        let error = NokhwaError::OpenStreamError("Invalid argument".to_string());
+        assert!(raises::<StreamClosedError>(&error));
+        assert!(raises::<DeviceNotFoundError>(&open_error(
+            "Invalid argument"
+        )));
+    }
+
+    #[test]
+    fn maps_property_errors() {
+        let set_error = |property: &str| NokhwaError::SetPropertyError {
+            property: property.to_string(),
+            value: "1".to_string(),
+            error: "Invalid argument".to_string(),
+        };
+        assert!(raises::<UnsupportedFormatError>(&set_error("CameraFormat")));
+        assert!(raises::<UnsupportedFormatError>(&set_error(
+            "Resolution, FrameFormat"
+        )));
+        assert!(raises::<ControlError>(&set_error("Brightness")));
+        let get_error = NokhwaError::GetPropertyError {
+            property: "Brightness".to_string(),
+            error: "Invalid argument".to_string(),
+        };
+        assert!(raises::<ControlError>(&get_error));
+        let error = NokhwaError::GeneralError("unknown".to_string());
+        assert!(raises::<CameraError>(&error));
+        assert!(!raises::<ControlError>(&error));
+    }
+}
@@ -0,0 +1,408 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, OnceLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use nokhwa::Buffer;
+use parking_lot::{Condvar, Mutex, MutexGuard};
+use pyo3::{
+    exceptions::PyValueError,
+    prelude::*,
+    types::{PyBytes, PyDict, PyTuple},
+};
+
+use crate::{frame_format_name, pixel::Image, wait_until_ready};
+
+static EPOCH: OnceLock<Instant> = OnceLock::new();
+
+/// Reference point for monotonic timestamps, pinned when the module is loaded.
+pub(crate) fn epoch() -> Instant {
+    *EPOCH.get_or_init(Instant::now)
+}
+
+/// A single captured frame.
+pub struct Frame {
+    /// Starts at 1 for the first frame captured by a camera and increases by one for every frame.
+    pub sequence: u64,
+    pub captured_at: Instant,
+    pub timestamp: SystemTime,
+    /// None if decoding is turned off.
+    pub image: Option<Image>,
+    /// Data as received from the camera, only kept if requested.
+    pub raw: Option<Buffer>,
+}
+
+#[cfg(test)]
+impl Frame {
+    /// A frame without any data, captured at *captured_at*.
+    pub(crate) fn blank(sequence: u64, captured_at: Instant) -> Arc<Frame> {
+        Arc::new(Frame {
+            sequence,
+            captured_at,
+            timestamp: SystemTime::now(),
+            image: None,
+            raw: None,
+        })
+    }
+}
+
+struct SlotState {
+    latest: Option<Arc<Frame>>,
+    /// Every captured frame in order, only used if queue_size is not 0.
+    queue: VecDeque<Arc<Frame>>,
+    queue_size: usize,
+    /// Frames evicted from a full queue before being read.
+    dropped: u64,
+    /// Whether latest has been taken by a consumer: get/wait_newer, a frame handler or the queue.
+    latest_seen: bool,
+    /// Frames replaced by a newer one before any consumer took them.
+    overwritten: u64,
+    /// Sequence number of the last stored frame, kept across reset() so that numbering continues.
+    last_sequence: u64,
+    /// Set once the capture thread has exited, so that waiters don't block forever.
+    closed: bool,
+}
+
+impl SlotState {
+    fn sequence(&self) -> u64 {
+        self.latest.as_ref().map_or(0, |frame| frame.sequence)
+    }
+}
+
+/// Holds the most recent frame (and optionally a queue of all frames),
+/// and wakes up threads waiting for new ones.
+pub(crate) struct FrameSlot {
+    state: Mutex<SlotState>,
+    new_frame: Condvar,
+}
+
+impl FrameSlot {
+    pub(crate) fn new() -> FrameSlot {
+        FrameSlot {
+            state: Mutex::new(SlotState {
+                latest: None,
+                queue: VecDeque::new(),
+                queue_size: 0,
+                dropped: 0,
+                latest_seen: false,
+                overwritten: 0,
+                last_sequence: 0,
+                closed: false,
+            }),
+            new_frame: Condvar::new(),
+        }
+    }
+    /// Enables queueing of up to `queue_size` frames, 0 disables the queue.
+    pub(crate) fn set_queue_size(&self, queue_size: usize) {
+        let mut state = self.state.lock();
+        state.queue_size = queue_size;
+        while state.queue.len() > queue_size {
+            state.queue.pop_front();
+            state.dropped += 1;
+        }
+    }
+    pub(crate) fn put(&self, frame: Arc<Frame>) {
+        let mut state = self.state.lock();
+        if state.queue_size > 0 {
+            if state.queue.len() == state.queue_size {
+                state.queue.pop_front();
+                state.dropped += 1;
+            }
+            state.queue.push_back(Arc::clone(&frame));
+        }
+        if state.latest.is_some() && !state.latest_seen {
+            state.overwritten += 1;
+        }
+        state.last_sequence = frame.sequence;
+        state.latest = Some(frame);
+        // Queued frames which are never read are counted as dropped instead
+        state.latest_seen = state.queue_size > 0;
+        self.new_frame.notify_all();
+    }
+    /// Records that a consumer other than get/wait_newer (e.g. a frame handler) took the frame with *sequence*.
+    pub(crate) fn taken(&self, sequence: u64) {
+        let mut state = self.state.lock();
+        if state.sequence() == sequence {
+            state.latest_seen = true;
+        }
+    }
+    /// Forgets stored frames and counters before the camera is opened again.
+    pub(crate) fn reset(&self) {
+        let mut state = self.state.lock();
+        state.latest = None;
+        state.queue.clear();
+        state.dropped = 0;
+        state.overwritten = 0;
+        state.closed = false;
+    }
+    pub(crate) fn last_sequence(&self) -> u64 {
+        self.state.lock().last_sequence
+    }
+    pub(crate) fn close(&self) {
+        self.state.lock().closed = true;
+        self.new_frame.notify_all();
+    }
+    pub(crate) fn get(&self) -> Option<Arc<Frame>> {
+        let mut state = self.state.lock();
+        state.latest_seen = true;
+        state.latest.clone()
+    }
+    /// Waits until `ready` returns true, the capture thread stops or the timeout expires.
+    fn wait_for(
+        &self,
+        timeout: Option<Duration>,
+        ready: impl Fn(&SlotState) -> bool,
+    ) -> MutexGuard<'_, SlotState> {
+        let mut state = self.state.lock();
+        wait_until_ready(&self.new_frame, &mut state, timeout, |state| {
+            ready(state) || state.closed
+        });
+        state
+    }
+    /// Blocks until a frame with a sequence number greater than `after` is available.
+    /// Returns None on timeout or if the capture thread has stopped.
+    pub(crate) fn wait_newer(&self, after: u64, timeout: Option<Duration>) -> Option<Arc<Frame>> {
+        let mut state = self.wait_for(timeout, |state| state.sequence() > after);
+        if state.sequence() > after {
+            state.latest_seen = true;
+            state.latest.clone()
+        } else {
+            None
+        }
+    }
+    /// Removes the oldest frame from the queue, blocking until there is one.
+    /// Returns None on timeout or if the capture thread has stopped.
+    pub(crate) fn pop(&self, timeout: Option<Duration>) -> Option<Arc<Frame>> {
+        self.wait_for(timeout, |state| !state.queue.is_empty())
+            .queue
+            .pop_front()
+    }
+    /// Removes all frames from the queue.
+    pub(crate) fn drain(&self) -> Vec<Arc<Frame>> {
+        self.state.lock().queue.drain(..).collect()
+    }
+    pub(crate) fn queue_size(&self) -> usize {
+        self.state.lock().queue_size
+    }
+    /// Number of frames in the queue.
+    pub(crate) fn queued(&self) -> usize {
+        self.state.lock().queue.len()
+    }
+    pub(crate) fn dropped(&self) -> u64 {
+        self.state.lock().dropped
+    }
+    pub(crate) fn overwritten(&self) -> u64 {
+        self.state.lock().overwritten
+    }
+}
+
+#[pyclass]
+pub(crate) struct CamFrame {
+    pub(crate) frame: Arc<Frame>,
+}
+
+impl CamFrame {
+    fn image(&self) -> PyResult<&Image> {
+        self.frame.image.as_ref().ok_or_else(|| {
+            PyValueError::new_err("Frame has not been decoded, open the camera with decode=True")
+        })
+    }
+    fn dimensions(&self) -> (u32, u32) {
+        match (&self.frame.image, &self.frame.raw) {
+            (Some(image), _) => (image.width, image.height),
+            (None, Some(raw)) => (raw.resolution().width(), raw.resolution().height()),
+            (None, None) => (0, 0),
+        }
+    }
+}
+
+#[pymethods]
+impl CamFrame {
+    #[getter]
+    fn width(&self) -> u32 {
+        self.dimensions().0
+    }
+    #[getter]
+    fn height(&self) -> u32 {
+        self.dimensions().1
+    }
+    /// Decoded pixel values, laid out according to pixel_format.
+    #[getter]
+    fn data<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        Ok(PyBytes::new(py, &self.image()?.data))
+    }
+    /// Exposes decoded pixels to numpy without copying them, use `numpy.asarray(frame)`.
+    /// The resulting array is read-only and keeps the frame alive.
+    #[getter(__array_interface__)]
+    fn array_interface<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let image = self.image()?;
+        let interface = PyDict::new(py);
+        interface.set_item("shape", PyTuple::new(py, image.shape())?)?;
+        interface.set_item("typestr", "|u1")?;
+        interface.set_item("data", (image.data.as_ptr() as usize, true))?;
+        interface.set_item("version", 3)?;
+        Ok(interface)
+    }
+    /// Layout of data, one of 'rgb', 'bgr', 'rgba', 'luma', 'i420'.
+    #[getter]
+    fn pixel_format(&self) -> Option<&'static str> {
+        self.frame.image.as_ref().map(|image| image.format.name())
+    }
+    /// Undecoded frame data as received from the camera, e.g. a JPEG image for mjpeg frames.
+    /// None unless the camera was opened with keep_raw=True.
+    #[getter]
+    fn raw_data<'py>(&self, py: Python<'py>) -> Option<Bound<'py, PyBytes>> {
+        self.frame
+            .raw
+            .as_ref()
+            .map(|raw| PyBytes::new(py, raw.buffer()))
+    }
+    /// Format of raw_data, one of 'mjpeg', 'yuyv', 'gray', 'nv12', 'rawrgb'.
+    #[getter]
+    fn raw_format(&self) -> Option<&'static str> {
+        self.frame
+            .raw
+            .as_ref()
+            .map(|raw| frame_format_name(raw.source_frame_format()))
+    }
+    #[getter]
+    fn sequence(&self) -> u64 {
+        self.frame.sequence
+    }
+    /// Wall-clock capture time, in seconds since the unix epoch.
+    #[getter]
+    fn timestamp(&self) -> f64 {
+        self.frame
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64()
+    }
+    /// Monotonic capture time, in seconds since the module was loaded.
+    /// Unaffected by system clock adjustments, use it to measure intervals between frames.
+    #[getter]
+    fn monotonic(&self) -> f64 {
+        self.frame
+            .captured_at
+            .saturating_duration_since(epoch())
+            .as_secs_f64()
+    }
+    /// Time passed since the frame was captured, in seconds.
+    #[getter]
+    fn age(&self) -> f64 {
+        self.frame.captured_at.elapsed().as_secs_f64()
+    }
+    fn __repr__(&self) -> String {
+        format!(
+            "CamFrame(sequence={}, width={}, height={}, timestamp={:.6})",
+            self.sequence(),
+            self.width(),
+            self.height(),
+            self.timestamp()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    fn frame(sequence: u64) -> Arc<Frame> {
+        Frame::blank(sequence, Instant::now())
+    }
+
+    #[test]
+    fn counts_frames_nobody_took_as_overwritten() {
+        let slot = FrameSlot::new();
+        slot.put(frame(1));
+        slot.put(frame(2));
+        assert_eq!(slot.overwritten(), 1);
+        assert_eq!(slot.get().unwrap().sequence, 2);
+        slot.put(frame(3));
+        assert_eq!(slot.overwritten(), 1);
+        slot.taken(3);
+        slot.put(frame(4));
+        assert_eq!(slot.overwritten(), 1);
+        // Only the latest frame can be taken
+        slot.taken(3);
+        slot.put(frame(5));
+        assert_eq!(slot.overwritten(), 2);
+    }
+
+    #[test]
+    fn full_queue_drops_oldest_frames() {
+        let slot = FrameSlot::new();
+        slot.set_queue_size(2);
+        for sequence in 1..=3 {
+            slot.put(frame(sequence));
+        }
+        assert_eq!(slot.dropped(), 1);
+        assert_eq!(slot.overwritten(), 0);
+        assert_eq!(slot.pop(Some(Duration::ZERO)).unwrap().sequence, 2);
+        let rest: Vec<u64> = slot.drain().iter().map(|frame| frame.sequence).collect();
+        assert_eq!(rest, [3]);
+        assert!(slot.pop(Some(Duration::ZERO)).is_none());
+    }
+
+    #[test]
+    fn reset_clears_frames_and_counters_but_not_numbering() {
+        let slot = FrameSlot::new();
+        slot.set_queue_size(1);
+        for sequence in 1..=3 {
+            slot.put(frame(sequence));
+        }
+        slot.set_queue_size(0);
+        slot.put(frame(4));
+        slot.put(frame(5));
+        slot.close();
+        slot.reset();
+        assert!(slot.get().is_none());
+        assert_eq!(slot.queued(), 0);
+        assert_eq!(slot.dropped(), 0);
+        assert_eq!(slot.overwritten(), 0);
+        assert_eq!(slot.last_sequence(), 5);
+        // Not closed anymore, so waiting times out
+        assert!(slot
+            .wait_newer(5, Some(Duration::from_millis(10)))
+            .is_none());
+    }
+
+    #[test]
+    fn wait_newer_returns_newer_frames_only() {
+        let slot = Arc::new(FrameSlot::new());
+        slot.put(frame(1));
+        assert_eq!(slot.wait_newer(0, None).unwrap().sequence, 1);
+        assert!(slot
+            .wait_newer(1, Some(Duration::from_millis(10)))
+            .is_none());
+        let waiter = {
+            let slot = Arc::clone(&slot);
+            thread::spawn(move || slot.wait_newer(1, Some(Duration::from_secs(5))))
+        };
+        slot.put(frame(2));
+        assert_eq!(waiter.join().unwrap().unwrap().sequence, 2);
+    }
+
+    #[test]
+    fn close_wakes_up_waiters() {
+        let slot = Arc::new(FrameSlot::new());
+        slot.set_queue_size(4);
+        let waiters: Vec<_> = (0..2)
+            .map(|i| {
+                let slot = Arc::clone(&slot);
+                thread::spawn(move || match i {
+                    0 => slot.wait_newer(0, None),
+                    _ => slot.pop(None),
+                })
+            })
+            .collect();
+        thread::sleep(Duration::from_millis(50));
+        slot.close();
+        for waiter in waiters {
+            assert!(waiter.join().unwrap().is_none());
+        }
+    }
+}
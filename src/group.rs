@@ -0,0 +1,312 @@
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use parking_lot::{Condvar, Mutex};
+use pyo3::{
+    exceptions::{PyIndexError, PyValueError},
+    prelude::*,
+};
+
+use crate::{
+    frame::{CamFrame, Frame},
+    handler::{Backpressure, Dispatcher, FrameHandler},
+    parse_timeout, wait_until_ready, Camera,
+};
+
+struct GroupState {
+    /// Recent frames of every camera which haven't been part of a returned set yet, oldest first.
+    histories: Vec<VecDeque<Arc<Frame>>>,
+    /// Cameras which stopped capturing, waiting for a set would be in vain.
+    stopped: Vec<bool>,
+}
+
+struct Shared {
+    state: Mutex<GroupState>,
+    new_frame: Condvar,
+}
+
+/// Feeds the frames of one camera into the group.
+struct Member {
+    shared: Arc<Shared>,
+    index: usize,
+    history: usize,
+}
+
+impl FrameHandler for Member {
+    fn on_frame(&mut self, frame: &Arc<Frame>) {
+        if frame.image.is_none() && frame.raw.is_none() {
+            return;
+        }
+        let mut state = self.shared.state.lock();
+        let history = &mut state.histories[self.index];
+        if history.len() == self.history {
+            history.pop_front();
+        }
+        history.push_back(Arc::clone(frame));
+        state.stopped[self.index] = false;
+        self.shared.new_frame.notify_all();
+    }
+    fn on_stop(&mut self) {
+        self.shared.state.lock().stopped[self.index] = true;
+        self.shared.new_frame.notify_all();
+    }
+}
+
+fn distance(a: Instant, b: Instant) -> Duration {
+    if a > b {
+        a - b
+    } else {
+        b - a
+    }
+}
+
+/// Index of the frame captured closest to *moment*.
+fn nearest(history: &VecDeque<Arc<Frame>>, moment: Instant) -> usize {
+    (0..history.len())
+        .min_by_key(|&i| distance(history[i].captured_at, moment))
+        .unwrap_or(0)
+}
+
+impl GroupState {
+    /// Picks a frame of every camera, all captured within *tolerance* of each other.
+    /// Returns the index of the picked frame in every history.
+    fn find_set(&self, tolerance: Duration) -> Option<Vec<usize>> {
+        // The camera whose newest frame is the oldest decides which moment the set shows,
+        // other cameras may already have moved past it but not the other way around.
+        let reference = self
+            .histories
+            .iter()
+            .map(|history| history.back().map(|frame| frame.captured_at))
+            .collect::<Option<Vec<_>>>()?
+            .into_iter()
+            .min()?;
+        let picks: Vec<usize> = self
+            .histories
+            .iter()
+            .map(|history| nearest(history, reference))
+            .collect();
+        let times = picks
+            .iter()
+            .zip(&self.histories)
+            .map(|(&i, history)| history[i].captured_at);
+        let first = times.clone().min()?;
+        let last = times.max()?;
+        (last - first <= tolerance).then_some(picks)
+    }
+    /// Removes a set found by find_set, along with older frames which can't be part of a later set.
+    fn take_set(&mut self, picks: &[usize]) -> FrameSet {
+        let frames: Vec<Arc<Frame>> = picks
+            .iter()
+            .zip(&mut self.histories)
+            .map(|(&i, history)| {
+                let frame = Arc::clone(&history[i]);
+                history.drain(..=i);
+                frame
+            })
+            .collect();
+        FrameSet::new(frames)
+    }
+}
+
+/// Frames of several cameras captured at (nearly) the same moment, one per camera in the order of the group.
+#[pyclass]
+pub(crate) struct FrameSet {
+    frames: Vec<Arc<Frame>>,
+    /// Time between the first and the last frame of the set was captured, in seconds.
+    #[pyo3(get)]
+    skew: f64,
+}
+
+impl FrameSet {
+    fn new(frames: Vec<Arc<Frame>>) -> FrameSet {
+        let times = frames.iter().map(|frame| frame.captured_at);
+        let skew = match (times.clone().min(), times.max()) {
+            (Some(first), Some(last)) => (last - first).as_secs_f64(),
+            _ => 0.0,
+        };
+        FrameSet { frames, skew }
+    }
+}
+
+#[pymethods]
+impl FrameSet {
+    #[getter]
+    fn frames(&self) -> Vec<CamFrame> {
+        self.frames
+            .iter()
+            .map(|frame| CamFrame {
+                frame: Arc::clone(frame),
+            })
+            .collect()
+    }
+    fn __len__(&self) -> usize {
+        self.frames.len()
+    }
+    fn __getitem__(&self, index: usize) -> PyResult<CamFrame> {
+        match self.frames.get(index) {
+            Some(frame) => Ok(CamFrame {
+                frame: Arc::clone(frame),
+            }),
+            None => Err(PyIndexError::new_err("FrameSet index out of range")),
+        }
+    }
+    fn __repr__(&self) -> String {
+        let sequences: Vec<u64> = self.frames.iter().map(|frame| frame.sequence).collect();
+        format!("FrameSet(sequences={sequences:?}, skew={:.6})", self.skew)
+    }
+}
+
+/// Matches up frames of several cameras by capture time.
+/// Every camera keeps capturing on its own thread, the group only listens to their frames.
+#[pyclass]
+pub(crate) struct CameraGroup {
+    shared: Arc<Shared>,
+    /// Handlers registered on the cameras, removed when the group is closed.
+    members: Mutex<Vec<(Arc<Dispatcher>, u64)>>,
+    tolerance: Duration,
+}
+
+impl CameraGroup {
+    fn close_members(&self) {
+        for (dispatcher, id) in self.members.lock().drain(..) {
+            dispatcher.remove(id);
+        }
+    }
+}
+
+impl Drop for CameraGroup {
+    fn drop(&mut self) {
+        self.close_members();
+    }
+}
+
+#[pymethods]
+impl CameraGroup {
+    /// Group *cameras*, frames captured within *tolerance* seconds of each other form a set.
+    /// Up to *history* recent frames of each camera are kept while looking for matches.
+    #[new]
+    #[pyo3(signature = (cameras, tolerance=0.01, history=8))]
+    fn new(cameras: Vec<PyRef<'_, Camera>>, tolerance: f64, history: usize) -> PyResult<Self> {
+        if cameras.is_empty() {
+            return Err(PyValueError::new_err(
+                "A camera group needs at least one camera",
+            ));
+        }
+        let tolerance = Duration::try_from_secs_f64(tolerance)
+            .map_err(|error| PyValueError::new_err(error.to_string()))?;
+        let shared = Arc::new(Shared {
+            state: Mutex::new(GroupState {
+                histories: vec![VecDeque::new(); cameras.len()],
+                stopped: vec![false; cameras.len()],
+            }),
+            new_frame: Condvar::new(),
+        });
+        let history = history.max(1);
+        let members = cameras
+            .iter()
+            .enumerate()
+            .map(|(index, camera)| {
+                let dispatcher = Arc::clone(&camera.cam.handlers);
+                let member = Member {
+                    shared: Arc::clone(&shared),
+                    index,
+                    history,
+                };
+                let id = dispatcher.add(Box::new(member), Backpressure::Drop, history);
+                (dispatcher, id)
+            })
+            .collect();
+        Ok(CameraGroup {
+            shared,
+            members: Mutex::new(members),
+            tolerance,
+        })
+    }
+
+    /// Return the newest set of matching frames not returned before, or None if there's none yet.
+    fn poll_frameset(&self) -> Option<FrameSet> {
+        let mut state = self.shared.state.lock();
+        let picks = state.find_set(self.tolerance)?;
+        Some(state.take_set(&picks))
+    }
+
+    /// Block until a new set of matching frames is available.
+    /// Returns None if *timeout* (in seconds) expires or one of the cameras has stopped capturing.
+    #[pyo3(signature = (timeout=None))]
+    fn wait_frameset(&self, py: Python, timeout: Option<f64>) -> PyResult<Option<FrameSet>> {
+        let timeout = parse_timeout(timeout)?;
+        Ok(py.allow_threads(|| {
+            let mut state = self.shared.state.lock();
+            wait_until_ready(&self.shared.new_frame, &mut state, timeout, |state| {
+                state.find_set(self.tolerance).is_some()
+                    || state.stopped.iter().any(|&stopped| stopped)
+            });
+            let picks = state.find_set(self.tolerance)?;
+            Some(state.take_set(&picks))
+        }))
+    }
+
+    /// Stop listening to the cameras, which keep capturing on their own.
+    fn close(&self, py: Python) {
+        py.allow_threads(|| self.close_members());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Histories of frames captured at the given offsets (in milliseconds), sequence numbers count from 1.
+    fn state(offsets: &[&[u64]]) -> GroupState {
+        let start = Instant::now();
+        GroupState {
+            histories: offsets
+                .iter()
+                .map(|offsets| {
+                    offsets
+                        .iter()
+                        .enumerate()
+                        .map(|(i, &offset)| {
+                            Frame::blank(i as u64 + 1, start + Duration::from_millis(offset))
+                        })
+                        .collect()
+                })
+                .collect(),
+            stopped: vec![false; offsets.len()],
+        }
+    }
+
+    #[test]
+    fn picks_frames_nearest_to_the_slowest_camera() {
+        let state = state(&[&[0, 33, 66, 100], &[30, 70]]);
+        assert_eq!(state.find_set(Duration::from_millis(10)), Some(vec![2, 1]));
+    }
+
+    #[test]
+    fn no_set_beyond_tolerance() {
+        let state = state(&[&[0, 40], &[20]]);
+        assert_eq!(state.find_set(Duration::from_millis(10)), None);
+        assert_eq!(state.find_set(Duration::from_millis(20)), Some(vec![0, 0]));
+    }
+
+    #[test]
+    fn no_set_without_a_frame_of_every_camera() {
+        let state = state(&[&[0, 33], &[]]);
+        assert_eq!(state.find_set(Duration::from_secs(1)), None);
+    }
+
+    #[test]
+    fn take_set_drops_older_frames() {
+        let mut state = state(&[&[0, 33, 66, 100], &[30, 70]]);
+        let picks = state.find_set(Duration::from_millis(10)).unwrap();
+        let set = state.take_set(&picks);
+        let sequences: Vec<u64> = set.frames.iter().map(|frame| frame.sequence).collect();
+        assert_eq!(sequences, [3, 2]);
+        assert_eq!(set.skew, 0.004);
+        assert_eq!(state.histories[0].len(), 1);
+        assert!(state.histories[1].is_empty());
+    }
+}
@@ -0,0 +1,260 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use parking_lot::{Condvar, FairMutex, Mutex};
+use pyo3::prelude::*;
+
+use crate::frame::{CamFrame, Frame};
+
+/// Receives every frame captured by a camera, called on a dispatcher thread of its own
+/// so that a slow handler doesn't hold up capture (unless it asked for that, see Backpressure).
+pub trait FrameHandler: Send {
+    fn on_frame(&mut self, frame: &Arc<Frame>);
+    /// Called after the last frame when capture stops, more frames follow if the camera is opened again.
+    fn on_stop(&mut self) {}
+}
+
+/// What happens to new frames while a handler is still busy with older ones.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Frames which don't fit in the handler's queue are skipped.
+    Drop,
+    /// Capture waits until there's room in the handler's queue.
+    /// The handler can't close or reopen the camera then, capture would wait for it forever.
+    Block,
+}
+
+enum Message {
+    Frame(Arc<Frame>),
+    /// Capture has stopped.
+    Stop,
+}
+
+struct MailboxState {
+    messages: VecDeque<Message>,
+    /// Frames among messages, only these count towards the queue size.
+    frames: usize,
+    /// Set once the handler is removed, it finishes the queued messages and exits.
+    closed: bool,
+}
+
+/// Messages waiting for a handler.
+struct Mailbox {
+    state: Mutex<MailboxState>,
+    changed: Condvar,
+    queue_size: usize,
+}
+
+impl Mailbox {
+    fn new(queue_size: usize) -> Mailbox {
+        Mailbox {
+            state: Mutex::new(MailboxState {
+                messages: VecDeque::new(),
+                frames: 0,
+                closed: false,
+            }),
+            changed: Condvar::new(),
+            queue_size: queue_size.max(1),
+        }
+    }
+    /// Returns false if the frame was skipped.
+    fn send_frame(&self, frame: &Arc<Frame>, backpressure: Backpressure) -> bool {
+        let mut state = self.state.lock();
+        while state.frames >= self.queue_size && !state.closed {
+            if backpressure == Backpressure::Drop {
+                return false;
+            }
+            self.changed.wait(&mut state);
+        }
+        if state.closed {
+            return false;
+        }
+        state.messages.push_back(Message::Frame(Arc::clone(frame)));
+        state.frames += 1;
+        self.changed.notify_all();
+        true
+    }
+    /// Never waits for room, so that stopping capture doesn't depend on the handler keeping up.
+    fn send_stop(&self) {
+        let mut state = self.state.lock();
+        if !state.closed {
+            state.messages.push_back(Message::Stop);
+            self.changed.notify_all();
+        }
+    }
+    fn close(&self) {
+        self.state.lock().closed = true;
+        self.changed.notify_all();
+    }
+    /// Waits for the next message, None once the mailbox is closed and empty.
+    fn receive(&self) -> Option<Message> {
+        let mut state = self.state.lock();
+        loop {
+            if let Some(message) = state.messages.pop_front() {
+                if let Message::Frame(_) = message {
+                    state.frames -= 1;
+                    // Capture may be waiting for room
+                    self.changed.notify_all();
+                }
+                return Some(message);
+            }
+            if state.closed {
+                return None;
+            }
+            self.changed.wait(&mut state);
+        }
+    }
+}
+
+struct Subscriber {
+    id: u64,
+    mailbox: Arc<Mailbox>,
+    backpressure: Backpressure,
+    thread: JoinHandle<()>,
+}
+
+/// Handlers registered on a camera, fed by the capture thread.
+pub(crate) struct Dispatcher {
+    subscribers: FairMutex<Vec<Subscriber>>,
+    next_id: AtomicU64,
+}
+
+impl Dispatcher {
+    pub(crate) fn new() -> Dispatcher {
+        Dispatcher {
+            subscribers: FairMutex::new(Vec::new()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+    /// Starts passing frames to *handler*, up to *queue_size* of them wait while it's busy.
+    /// Returns an id for remove.
+    pub(crate) fn add(
+        &self,
+        mut handler: Box<dyn FrameHandler>,
+        backpressure: Backpressure,
+        queue_size: usize,
+    ) -> u64 {
+        let mailbox = Arc::new(Mailbox::new(queue_size));
+        let thread = {
+            let mailbox = Arc::clone(&mailbox);
+            thread::spawn(move || {
+                while let Some(message) = mailbox.receive() {
+                    match message {
+                        Message::Frame(frame) => handler.on_frame(&frame),
+                        Message::Stop => handler.on_stop(),
+                    }
+                }
+            })
+        };
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.subscribers.lock().push(Subscriber {
+            id,
+            mailbox,
+            backpressure,
+            thread,
+        });
+        id
+    }
+    /// Stops passing frames to a handler and waits for it to finish the queued ones.
+    /// Returns false if there's no handler with this id.
+    pub(crate) fn remove(&self, id: u64) -> bool {
+        let subscriber = {
+            let mut subscribers = self.subscribers.lock();
+            match subscribers
+                .iter()
+                .position(|subscriber| subscriber.id == id)
+            {
+                Some(index) => subscribers.remove(index),
+                None => return false,
+            }
+        };
+        subscriber.mailbox.close();
+        // A handler removing itself can't wait for its own thread
+        if subscriber.thread.thread().id() != thread::current().id() {
+            let _ = subscriber.thread.join();
+        }
+        true
+    }
+    /// Stops passing frames to all handlers, without waiting for them to finish.
+    pub(crate) fn clear(&self) {
+        for subscriber in self.subscribers.lock().drain(..) {
+            subscriber.mailbox.close();
+        }
+    }
+    /// Whether the current thread is the one of a handler which capture waits for (Backpressure::Block),
+    /// from where waiting for capture to stop or switch formats would never end.
+    pub(crate) fn on_blocking_handler(&self) -> bool {
+        let current = thread::current().id();
+        self.subscribers.lock().iter().any(|subscriber| {
+            subscriber.backpressure == Backpressure::Block
+                && subscriber.thread.thread().id() == current
+        })
+    }
+    /// Mailboxes are cloned so that a blocking send doesn't keep handlers from being added or removed.
+    fn targets(&self) -> Vec<(Arc<Mailbox>, Backpressure)> {
+        self.subscribers
+            .lock()
+            .iter()
+            .map(|subscriber| (Arc::clone(&subscriber.mailbox), subscriber.backpressure))
+            .collect()
+    }
+    /// Returns whether any handler took the frame.
+    pub(crate) fn dispatch(&self, frame: &Arc<Frame>) -> bool {
+        let mut taken = false;
+        for (mailbox, backpressure) in self.targets() {
+            taken |= mailbox.send_frame(frame, backpressure);
+        }
+        taken
+    }
+    /// Tells handlers that capture has stopped, this is never skipped and never blocks.
+    pub(crate) fn stopped(&self) {
+        for (mailbox, _) in self.targets() {
+            mailbox.send_stop();
+        }
+    }
+}
+
+/// Calls a python callable with every frame, and optionally another one when capture stops.
+pub(crate) struct PyFrameHandler {
+    callback: Py<PyAny>,
+    on_stop: Option<Py<PyAny>>,
+}
+
+impl PyFrameHandler {
+    pub(crate) fn new(callback: Py<PyAny>, on_stop: Option<Py<PyAny>>) -> PyFrameHandler {
+        PyFrameHandler { callback, on_stop }
+    }
+}
+
+impl FrameHandler for PyFrameHandler {
+    fn on_frame(&mut self, frame: &Arc<Frame>) {
+        if frame.image.is_none() && frame.raw.is_none() {
+            return; // Nothing to hand out, same as poll_frame
+        }
+        Python::with_gil(|py| {
+            let frame = CamFrame {
+                frame: Arc::clone(frame),
+            };
+            if let Err(error) = self.callback.call1(py, (frame,)) {
+                // There's no caller to raise to
+                error.write_unraisable(py, Some(self.callback.bind(py)));
+            }
+        });
+    }
+    fn on_stop(&mut self) {
+        let Some(on_stop) = &self.on_stop else {
+            return;
+        };
+        Python::with_gil(|py| {
+            if let Err(error) = on_stop.call0(py) {
+                error.write_unraisable(py, Some(on_stop.bind(py)));
+            }
+        });
+    }
+}
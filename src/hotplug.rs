@@ -0,0 +1,405 @@
+use std::{
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use nokhwa::NokhwaError;
+use parking_lot::{Condvar, Mutex};
+use pyo3::{exceptions::PyKeyError, exceptions::PyValueError, prelude::*, types::PyTuple};
+
+use crate::{
+    backend::Backend,
+    device::{self, DeviceInfo},
+    errors, parse_backend, parse_timeout, sleep_while_active, wait_until_ready,
+};
+
+/// A camera which was connected or disconnected.
+#[derive(Clone)]
+#[pyclass]
+pub(crate) struct DeviceEvent {
+    /// 'added' or 'removed'.
+    #[pyo3(get)]
+    kind: &'static str,
+    #[pyo3(get)]
+    device: DeviceInfo,
+}
+
+#[pymethods]
+impl DeviceEvent {
+    fn __repr__(&self) -> String {
+        format!(
+            "DeviceEvent(kind={:?}, stable_id={:?})",
+            self.kind,
+            self.device.stable_id()
+        )
+    }
+}
+
+/// Number of videoN device nodes, e.g. 2 for video2.
+fn node_number(name: &str) -> Option<u32> {
+    name.strip_prefix("video")?.parse().ok()
+}
+
+/// Lists videoN entries of *dir*, sorted.
+fn list_nodes(dir: &Path) -> Vec<(u32, PathBuf)> {
+    let mut nodes: Vec<(u32, PathBuf)> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let number = node_number(entry.file_name().to_str()?)?;
+            Some((number, entry.path()))
+        })
+        .collect();
+    nodes.sort();
+    nodes
+}
+
+/// Where the watcher gets the list of cameras from.
+enum Scanner {
+    /// Cameras listed by a backend.
+    Devices {
+        backend: Backend,
+        test_patterns: bool,
+    },
+    /// Device nodes in a directory other than /dev, without asking the backend about them.
+    Directory(PathBuf),
+}
+
+impl Scanner {
+    /// Cheap to compute summary of connected devices, the full scan is only done when it changes.
+    /// None if there's no cheap way to tell, in which case every poll does a full scan.
+    fn fingerprint(&self) -> Option<Vec<(u32, PathBuf)>> {
+        match self {
+            Scanner::Directory(dir) => Some(list_nodes(dir)),
+            Scanner::Devices {
+                backend: Backend::Test,
+                ..
+            } => Some(Vec::new()),
+            Scanner::Devices { .. } if cfg!(target_os = "linux") => {
+                Some(list_nodes(Path::new("/dev")))
+            }
+            Scanner::Devices { .. } => None,
+        }
+    }
+    fn scan(&self) -> Result<Vec<DeviceInfo>, NokhwaError> {
+        match self {
+            Scanner::Devices {
+                backend,
+                test_patterns,
+            } => device::query_devices(*test_patterns, *backend),
+            Scanner::Directory(dir) => Ok(list_nodes(dir)
+                .into_iter()
+                .map(|(number, path)| DeviceInfo::from_node(&path, number))
+                .collect()),
+        }
+    }
+}
+
+/// Compares two scans by stable id.
+fn diff(before: &[DeviceInfo], after: &[DeviceInfo]) -> Vec<DeviceEvent> {
+    let contains = |devices: &[DeviceInfo], device: &DeviceInfo| {
+        let id = device.stable_id();
+        devices.iter().any(|other| other.stable_id() == id)
+    };
+    let removed = before
+        .iter()
+        .filter(|device| !contains(after, device))
+        .map(|device| DeviceEvent {
+            kind: "removed",
+            device: device.clone(),
+        });
+    let added = after
+        .iter()
+        .filter(|device| !contains(before, device))
+        .map(|device| DeviceEvent {
+            kind: "added",
+            device: device.clone(),
+        });
+    removed.chain(added).collect()
+}
+
+/// Events kept for poll_event/wait_event, older ones are discarded if nobody reads them.
+const EVENT_QUEUE_SIZE: usize = 64;
+
+struct Callback {
+    id: u64,
+    on_event: Py<PyAny>,
+    /// Called once the watcher stops.
+    on_close: Option<Py<PyAny>>,
+}
+
+struct Shared {
+    devices: Mutex<Vec<DeviceInfo>>,
+    events: Mutex<VecDeque<DeviceEvent>>,
+    new_event: Condvar,
+    callbacks: Mutex<Vec<Callback>>,
+}
+
+impl Shared {
+    /// Calls the callables *pick* returns for every callback, with *args*.
+    fn call(
+        &self,
+        pick: impl Fn(&Callback) -> Option<&Py<PyAny>>,
+        args: impl for<'py> Fn(Python<'py>) -> PyResult<Bound<'py, PyTuple>>,
+    ) {
+        if self.callbacks.lock().is_empty() {
+            return;
+        }
+        Python::with_gil(|py| {
+            // Copied so that callbacks can add and remove callbacks
+            let callables: Vec<Py<PyAny>> = self
+                .callbacks
+                .lock()
+                .iter()
+                .filter_map(|callback| pick(callback).map(|callable| callable.clone_ref(py)))
+                .collect();
+            for callable in &callables {
+                if let Err(error) = args(py).and_then(|args| callable.call1(py, args)) {
+                    error.write_unraisable(py, Some(callable.bind(py)));
+                }
+            }
+        });
+    }
+    fn emit(&self, event: DeviceEvent) {
+        {
+            let mut events = self.events.lock();
+            if events.len() == EVENT_QUEUE_SIZE {
+                events.pop_front();
+            }
+            events.push_back(event.clone());
+        }
+        self.new_event.notify_all();
+        self.call(
+            |callback| Some(&callback.on_event),
+            |py| PyTuple::new(py, [event.clone()]),
+        );
+    }
+    fn closed(&self) {
+        {
+            // Waiters check whether the watcher is active under the events lock, so that they can't miss this
+            let _events = self.events.lock();
+            self.new_event.notify_all();
+        }
+        self.call(
+            |callback| callback.on_close.as_ref(),
+            |py| Ok(PyTuple::empty(py)),
+        );
+    }
+}
+
+/// Device nodes may show up before the backend can open them, so a change is rescanned this many more times.
+const SETTLE_SCANS: u32 = 2;
+
+/// Watches for cameras being connected and disconnected, on a thread of its own.
+/// Events can be polled, waited for, or delivered to callbacks.
+#[pyclass]
+pub(crate) struct DeviceWatcher {
+    shared: Arc<Shared>,
+    active: Arc<AtomicBool>,
+    next_callback_id: AtomicU64,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl DeviceWatcher {
+    fn stop(&self) {
+        self.active.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.lock().take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for DeviceWatcher {
+    fn drop(&mut self) {
+        // The watcher thread may be waiting for the GIL to call a callback
+        Python::with_gil(|py| py.allow_threads(|| self.stop()));
+    }
+}
+
+#[pymethods]
+impl DeviceWatcher {
+    /// Check for changes every *interval* seconds. On Linux, the backend is only asked for cameras
+    /// when device nodes in /dev change. With *dev_dir*, device nodes (videoN) in that directory are watched
+    /// instead of asking the backend at all, which allows testing without cameras.
+    #[new]
+    #[pyo3(signature = (interval=0.5, backend="auto", test_patterns=false, dev_dir=None))]
+    fn new(
+        interval: f64,
+        backend: &str,
+        test_patterns: bool,
+        dev_dir: Option<PathBuf>,
+    ) -> PyResult<Self> {
+        let interval = parse_timeout(Some(interval))?.unwrap_or_default();
+        if interval.is_zero() {
+            return Err(PyValueError::new_err("Interval must be positive"));
+        }
+        let scanner = match dev_dir {
+            Some(dir) => Scanner::Directory(dir),
+            None => Scanner::Devices {
+                backend: parse_backend(backend)?,
+                test_patterns,
+            },
+        };
+        let mut fingerprint = scanner.fingerprint();
+        let initial = scanner.scan().map_err(|error| errors::to_py(&error))?;
+        let shared = Arc::new(Shared {
+            devices: Mutex::new(initial),
+            events: Mutex::new(VecDeque::new()),
+            new_event: Condvar::new(),
+            callbacks: Mutex::new(Vec::new()),
+        });
+        let active = Arc::new(AtomicBool::new(true));
+        let thread = {
+            let shared = Arc::clone(&shared);
+            let active = Arc::clone(&active);
+            thread::spawn(move || {
+                let mut pending_scans = 0;
+                while active.load(Ordering::Relaxed) {
+                    sleep_while_active(&active, interval);
+                    let current = scanner.fingerprint();
+                    if current.is_none() || current != fingerprint {
+                        pending_scans = SETTLE_SCANS + 1;
+                        fingerprint = current;
+                    }
+                    if pending_scans == 0 || !active.load(Ordering::Relaxed) {
+                        continue;
+                    }
+                    pending_scans -= 1;
+                    let Ok(devices) = scanner.scan() else {
+                        continue;
+                    };
+                    let events = diff(&shared.devices.lock(), &devices);
+                    *shared.devices.lock() = devices;
+                    for event in events {
+                        shared.emit(event);
+                    }
+                }
+                shared.closed();
+            })
+        };
+        Ok(DeviceWatcher {
+            shared,
+            active,
+            next_callback_id: AtomicU64::new(1),
+            thread: Mutex::new(Some(thread)),
+        })
+    }
+
+    /// Cameras connected as of the last check.
+    fn devices(&self) -> Vec<DeviceInfo> {
+        self.shared.devices.lock().clone()
+    }
+
+    /// Remove and return the oldest event, None if there are none.
+    /// Only the last 64 events are kept.
+    fn poll_event(&self) -> Option<DeviceEvent> {
+        self.shared.events.lock().pop_front()
+    }
+
+    /// Remove and return the oldest event, waiting for one if there are none.
+    /// Returns None if *timeout* (in seconds) expires.
+    #[pyo3(signature = (timeout=None))]
+    fn wait_event(&self, py: Python, timeout: Option<f64>) -> PyResult<Option<DeviceEvent>> {
+        let timeout = parse_timeout(timeout)?;
+        Ok(py.allow_threads(|| {
+            let mut events = self.shared.events.lock();
+            // A closed watcher wakes up waiters, see Shared::closed
+            wait_until_ready(&self.shared.new_event, &mut events, timeout, |events| {
+                !events.is_empty() || !self.active.load(Ordering::Relaxed)
+            });
+            events.pop_front()
+        }))
+    }
+
+    /// Call *callback* with every event, from the watcher thread. Events are still queued for poll_event.
+    /// *on_close* is called once the watcher stops. Returns an id for remove_callback.
+    #[pyo3(signature = (callback, on_close=None))]
+    fn add_callback(&self, callback: Py<PyAny>, on_close: Option<Py<PyAny>>) -> u64 {
+        let id = self.next_callback_id.fetch_add(1, Ordering::Relaxed);
+        self.shared.callbacks.lock().push(Callback {
+            id,
+            on_event: callback,
+            on_close,
+        });
+        id
+    }
+
+    fn remove_callback(&self, id: u64) -> PyResult<()> {
+        let mut callbacks = self.shared.callbacks.lock();
+        let before = callbacks.len();
+        callbacks.retain(|callback| callback.id != id);
+        if callbacks.len() != before {
+            Ok(())
+        } else {
+            Err(PyKeyError::new_err(id))
+        }
+    }
+
+    /// Stop watching.
+    fn close(&self, py: Python) {
+        py.allow_threads(|| self.stop());
+    }
+
+    /// Whether the watcher has stopped, no more events will follow.
+    fn is_closed(&self) -> bool {
+        !self.active.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(number: u32) -> DeviceInfo {
+        DeviceInfo::from_node(Path::new(&format!("/dev/video{number}")), number)
+    }
+
+    #[test]
+    fn diff_reports_removed_then_added() {
+        let events = diff(&[node(0), node(1)], &[node(1), node(2)]);
+        let summary: Vec<(&str, String)> = events
+            .iter()
+            .map(|event| (event.kind, event.device.stable_id()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("removed", node(0).stable_id()),
+                ("added", node(2).stable_id())
+            ]
+        );
+        assert!(diff(&[node(0)], &[node(0)]).is_empty());
+    }
+
+    #[test]
+    fn event_queue_is_bounded() {
+        let shared = Shared {
+            devices: Mutex::new(Vec::new()),
+            events: Mutex::new(VecDeque::new()),
+            new_event: Condvar::new(),
+            callbacks: Mutex::new(Vec::new()),
+        };
+        for number in 0..EVENT_QUEUE_SIZE as u32 + 2 {
+            for event in diff(&[], &[node(number)]) {
+                shared.emit(event);
+            }
+        }
+        let events = shared.events.lock();
+        assert_eq!(events.len(), EVENT_QUEUE_SIZE);
+        assert_eq!(events[0].device.stable_id(), node(2).stable_id());
+    }
+
+    #[test]
+    fn node_numbers() {
+        assert_eq!(node_number("video12"), Some(12));
+        assert_eq!(node_number("video"), None);
+        assert_eq!(node_number("media0"), None);
+    }
+}
@@ -1,112 +1,465 @@
+mod backend;
+mod control;
+mod device;
+mod errors;
+mod frame;
+mod group;
+mod handler;
+mod hotplug;
+mod pixel;
+mod playback;
+mod recorder;
+mod source;
+mod stats;
+mod test_pattern;
+
 use std::{
-    mem,
-    sync::{atomic, Arc, Mutex, Weak},
+    path::PathBuf,
+    sync::{atomic, mpsc, Arc, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime},
 };
 
-use image::{ImageBuffer, Rgb};
-use nokhwa::{
-    pixel_format::RgbFormat,
-    utils::{
-        ApiBackend, CameraControl, CameraFormat, CameraIndex, ControlValueDescription, FrameFormat,
-        RequestedFormat, RequestedFormatType,
-    },
-};
-use parking_lot::FairMutex;
+use backend::Backend;
+use control::CamControl;
+use device::DeviceInfo;
+use errors::{CameraError, StreamClosedError};
+use frame::{CamFrame, FrameSlot};
+use group::{CameraGroup, FrameSet};
+use handler::{Dispatcher, PyFrameHandler};
+use hotplug::{DeviceEvent, DeviceWatcher};
+use nokhwa::utils::{CameraFormat, CameraIndex, FrameFormat};
+use parking_lot::{Condvar, FairMutex, MutexGuard};
 use pyo3::{
-    exceptions::{PyRuntimeError, PyValueError},
+    exceptions::{PyKeyError, PyValueError},
     prelude::*,
-    types::PyBytes,
 };
+use recorder::AviWriter;
+use source::FrameSource;
+use stats::{CaptureStats, StatsCollector};
+
+// Rust API for code embedding this crate, e.g. to process frames without going through python:
+// extract a Camera from the object python code opened and register a FrameHandler on it.
+pub use frame::Frame;
+pub use handler::{Backpressure, FrameHandler};
+pub use pixel::{Image, PixelFormat};
 
+/// Names of the backends compiled into this build, usable as the backend argument.
 #[pyfunction]
-pub fn query() -> PyResult<Vec<(u32, String, String, String)>> {
-    let devices = match nokhwa::query(ApiBackend::Auto) {
-        Ok(val) => val,
-        Err(error) => return Err(PyRuntimeError::new_err(error.to_string())),
-    };
-    let mut result = Vec::with_capacity(devices.len());
-    for device in devices.into_iter() {
-        if let CameraIndex::Index(index) = *device.index() {
-            result.push((
-                index,
-                device.human_name(),
-                device.description().to_owned(),
-                device.misc(),
-            ));
-        }
+pub fn backends() -> Vec<&'static str> {
+    Backend::available()
+        .into_iter()
+        .map(Backend::name)
+        .collect()
+}
+
+fn parse_backend(name: &str) -> PyResult<Backend> {
+    Backend::from_name(name).ok_or_else(|| {
+        PyValueError::new_err(format!(
+            "Unsupported backend (should be one of {})",
+            Backend::NAMES
+        ))
+    })
+}
+
+#[pyfunction]
+#[pyo3(signature = (test_patterns=false, backend="auto"))]
+pub fn query(test_patterns: bool, backend: &str) -> PyResult<Vec<DeviceInfo>> {
+    device::query_devices(test_patterns, parse_backend(backend)?)
+        .map_err(|error| errors::to_py(&error))
+}
+
+/// Find a connected camera by DeviceInfo.stable_id, None if it isn't connected.
+#[pyfunction]
+#[pyo3(signature = (stable_id, backend="auto"))]
+pub fn find_device(stable_id: &str, backend: &str) -> PyResult<Option<DeviceInfo>> {
+    Ok(device::find_device(stable_id, None, parse_backend(backend)?).ok())
+}
+
+/// Resolves what python passed to identify a camera: an index, a DeviceInfo or a string.
+/// Cameras given by DeviceInfo are opened by its index, with the backend which listed them.
+fn camera_index(target: &Bound<'_, PyAny>, backend: &str) -> PyResult<(CameraIndex, Backend)> {
+    if let Ok(device) = target.downcast::<DeviceInfo>() {
+        let device = device.borrow();
+        return Ok((device.camera_index.clone(), device.backend));
+    }
+    let backend = parse_backend(backend)?;
+    if let Ok(name) = target.extract::<String>() {
+        return device::resolve_name(&name, backend)
+            .map(|index| (index, backend))
+            .map_err(|error| errors::to_py(&error));
     }
-    Ok(result)
+    Ok((CameraIndex::Index(target.extract()?), backend))
 }
 
 #[pyfunction]
-pub fn check_can_use(index: u32) -> PyResult<bool> {
-    Ok(nokhwa::Camera::new(
-        CameraIndex::Index(index),
-        RequestedFormat::new::<RgbFormat>(RequestedFormatType::None),
-    )
-    .is_ok())
+#[pyo3(signature = (index, backend="auto"))]
+pub fn check_can_use(index: &Bound<'_, PyAny>, backend: &str) -> PyResult<bool> {
+    let (index, backend) = camera_index(index, backend)?;
+    Ok(source::open_source(index, backend).is_ok())
 }
 
 #[pymodule]
 fn omni_camera(_py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
     nokhwa::nokhwa_initialize(|_| {});
+    frame::epoch();
+    m.add_function(wrap_pyfunction!(backends, m)?)?;
     m.add_function(wrap_pyfunction!(query, m)?)?;
     m.add_function(wrap_pyfunction!(check_can_use, m)?)?;
+    m.add_function(wrap_pyfunction!(find_device, m)?)?;
     m.add_class::<Camera>()?;
     m.add_class::<CamFormat>()?;
     m.add_class::<CamControl>()?;
+    m.add_class::<CamFrame>()?;
+    m.add_class::<DeviceInfo>()?;
+    m.add_class::<CaptureStats>()?;
+    m.add_class::<CameraGroup>()?;
+    m.add_class::<FrameSet>()?;
+    m.add_class::<DeviceWatcher>()?;
+    m.add_class::<DeviceEvent>()?;
+    errors::register(m)?;
     Ok(())
 }
 
-type Image = ImageBuffer<Rgb<u8>, Vec<u8>>;
+/// Settings of the capture thread, chosen when the camera is opened.
+#[derive(Clone, Copy)]
+struct CaptureOptions {
+    /// Keep undecoded frame data along with the decoded image.
+    keep_raw: bool,
+    /// Decode frames to pixel_format, can be turned off if only undecoded data is needed.
+    decode: bool,
+    pixel_format: PixelFormat,
+    /// Keep up to this many frames queued for read_frames/next_frame, 0 disables the queue.
+    queue_size: usize,
+    /// Consider the camera disconnected after this many frames failed to read in a row.
+    max_errors: u32,
+    /// Try to reconnect a disconnected camera this often, None stops capture instead.
+    reconnect_interval: Option<Duration>,
+}
+
+/// Pause after a failed read, so that a broken camera doesn't keep the capture thread spinning.
+const ERROR_BACKOFF: Duration = Duration::from_millis(10);
+
+/// What the capture thread is doing, reported by Camera.state.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum StreamState {
+    Closed,
+    Streaming,
+    /// The camera was lost and capture has stopped, or is about to be reconnected.
+    Disconnected,
+    Reconnecting,
+}
+
+impl StreamState {
+    fn name(self) -> &'static str {
+        match self {
+            StreamState::Closed => "closed",
+            StreamState::Streaming => "streaming",
+            StreamState::Disconnected => "disconnected",
+            StreamState::Reconnecting => "reconnecting",
+        }
+    }
+}
+
+/// Sleeps for *duration*, waking up early if capture is stopped.
+fn sleep_while_active(active: &atomic::AtomicBool, duration: Duration) {
+    let until = Instant::now() + duration;
+    while active.load(atomic::Ordering::Relaxed) {
+        let now = Instant::now();
+        if now >= until {
+            break;
+        }
+        std::thread::sleep((until - now).min(Duration::from_millis(50)));
+    }
+}
+
+/// Waits on *condvar* until *ready* returns true or *timeout* expires (never if None).
+/// *ready* has to become true under the mutex of *guard*, or *condvar* has to be notified
+/// while holding it, otherwise the wakeup can be missed.
+fn wait_until_ready<T>(
+    condvar: &Condvar,
+    guard: &mut MutexGuard<'_, T>,
+    timeout: Option<Duration>,
+    ready: impl Fn(&T) -> bool,
+) {
+    let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+    while !ready(guard) {
+        match deadline {
+            Some(deadline) => {
+                if condvar.wait_until(guard, deadline).timed_out() {
+                    return;
+                }
+            }
+            None => condvar.wait(guard),
+        }
+    }
+}
+
+/// A format the capture thread should switch to.
+struct FormatSwitch {
+    format: CameraFormat,
+    /// Receives the result of the switch, otherwise a failure is stored in format_err.
+    done: Option<mpsc::SyncSender<Result<(), nokhwa::NokhwaError>>>,
+}
 
 struct CameraInternal {
-    camera: Arc<FairMutex<nokhwa::Camera>>,
+    camera: Arc<FairMutex<Box<dyn FrameSource>>>,
     active: Arc<atomic::AtomicBool>,
-    last_frame: Arc<FairMutex<Arc<Option<Image>>>>,
+    state: Arc<FairMutex<StreamState>>,
+    last_frame: Arc<FrameSlot>,
     last_err: Arc<FairMutex<Option<nokhwa::NokhwaError>>>,
+    /// Failed format switch, capture went on in the previous format. Reported once by check_err.
+    format_err: Arc<FairMutex<Option<nokhwa::NokhwaError>>>,
+    recorder: Arc<FairMutex<Option<AviWriter>>>,
+    stats: Arc<FairMutex<StatsCollector>>,
+    handlers: Arc<Dispatcher>,
+    /// Format to switch to, applied by the capture thread.
+    pending_format: Arc<FairMutex<Option<FormatSwitch>>>,
+    /// Capture thread, returns the result of closing the stream.
+    thread: FairMutex<Option<JoinHandle<Result<(), nokhwa::NokhwaError>>>>,
 }
 
 impl CameraInternal {
-    fn new(cam: nokhwa::Camera) -> CameraInternal {
+    fn new(cam: Box<dyn FrameSource>) -> CameraInternal {
         CameraInternal {
             camera: Arc::new(FairMutex::new(cam)),
-            active: Arc::new(atomic::AtomicBool::new(true)),
-            last_frame: Arc::new(FairMutex::new(Arc::new(None))),
+            active: Arc::new(atomic::AtomicBool::new(false)),
+            state: Arc::new(FairMutex::new(StreamState::Closed)),
+            last_frame: Arc::new(FrameSlot::new()),
             last_err: Arc::new(FairMutex::new(None)),
+            format_err: Arc::new(FairMutex::new(None)),
+            recorder: Arc::new(FairMutex::new(None)),
+            stats: Arc::new(FairMutex::new(StatsCollector::default())),
+            handlers: Arc::new(Dispatcher::new()),
+            pending_format: Arc::new(FairMutex::new(None)),
+            thread: FairMutex::new(None),
         }
     }
-    fn start(&self, format: CameraFormat) -> Result<(), nokhwa::NokhwaError> {
+    /// Starts the capture thread and waits until the stream is open in *format*.
+    fn start(
+        &self,
+        format: CameraFormat,
+        options: CaptureOptions,
+    ) -> Result<(), nokhwa::NokhwaError> {
+        self.stop()?;
+        self.last_frame.reset();
+        self.last_frame.set_queue_size(options.queue_size);
+        *self.last_err.lock() = None;
+        *self.format_err.lock() = None;
+        *self.pending_format.lock() = None;
+        *self.stats.lock() = StatsCollector::default();
+        self.active.store(true, atomic::Ordering::Relaxed);
         let active = Arc::clone(&self.active);
+        let state = Arc::clone(&self.state);
         let last_frame = Arc::clone(&self.last_frame);
         let camera = Arc::clone(&self.camera);
         let last_err = Arc::clone(&self.last_err);
-        std::thread::spawn(move || {
-            let mut cam_guard = camera.lock();
-            if let Err(err) = cam_guard
-                .set_camera_format(format)
-                .and(cam_guard.open_stream())
-            {
-                *last_err.lock() = Some(err);
-                return;
+        let format_err = Arc::clone(&self.format_err);
+        let recorder = Arc::clone(&self.recorder);
+        let stats = Arc::clone(&self.stats);
+        let handlers = Arc::clone(&self.handlers);
+        let pending_format = Arc::clone(&self.pending_format);
+        let (opened_tx, opened_rx) = mpsc::sync_channel(1);
+        let thread = std::thread::spawn(move || {
+            let opened = {
+                let mut cam = camera.lock();
+                cam.set_camera_format(format)
+                    .and_then(|()| cam.open_stream())
+            };
+            let failed = opened.is_err();
+            if !failed {
+                *state.lock() = StreamState::Streaming;
+            }
+            let _ = opened_tx.send(opened);
+            if failed {
+                last_frame.close();
+                handlers.stopped();
+                return Ok(());
             }
-            mem::drop(cam_guard);
+            let mut sequence = last_frame.last_sequence();
+            let mut failures = 0;
+            let mut disconnected = false;
             while active.load(atomic::Ordering::Relaxed) {
-                if let Ok(frame) = camera.lock().frame() {
-                    *last_frame.lock() = Arc::new(frame.decode_image::<RgbFormat>().ok());
+                let switch = pending_format.lock().take();
+                if let Some(switch) = switch {
+                    let result = camera.lock().switch_format(switch.format);
+                    match (switch.done, result) {
+                        (Some(done), result) => {
+                            let _ = done.send(result);
+                        }
+                        (None, Err(err)) => *format_err.lock() = Some(err),
+                        (None, Ok(())) => {}
+                    }
+                }
+                let result = camera.lock().frame();
+                let frame = match result {
+                    Ok(frame) => {
+                        failures = 0;
+                        frame
+                    }
+                    Err(_) if camera.lock().at_end() => break,
+                    Err(err) => {
+                        // Failures which don't stop capture are only counted, see stats()
+                        failures += 1;
+                        stats.lock().read_failed(&err);
+                        if failures < options.max_errors && camera.lock().is_connected() {
+                            std::thread::sleep(ERROR_BACKOFF);
+                            continue;
+                        }
+                        *state.lock() = StreamState::Disconnected;
+                        let Some(interval) = options.reconnect_interval else {
+                            *last_err.lock() = Some(err);
+                            disconnected = true;
+                            break;
+                        };
+                        *state.lock() = StreamState::Reconnecting;
+                        while active.load(atomic::Ordering::Relaxed) {
+                            sleep_while_active(&active, interval);
+                            if active.load(atomic::Ordering::Relaxed)
+                                && camera.lock().reconnect().is_ok()
+                            {
+                                *state.lock() = StreamState::Streaming;
+                                failures = 0;
+                                break;
+                            }
+                        }
+                        continue;
+                    }
+                };
+                let captured_at = Instant::now();
+                let timestamp = SystemTime::now();
+                let decode_started = Instant::now();
+                let image = if options.decode {
+                    match pixel::decode(&frame, options.pixel_format) {
+                        Ok(image) => Some(image),
+                        Err(err) => {
+                            // Skip the frame, so that the last good one stays available
+                            stats.lock().decode_failed(&err);
+                            continue;
+                        }
+                    }
+                } else {
+                    None
+                };
+                let decode_time = options.decode.then(|| decode_started.elapsed());
+                stats.lock().frame(captured_at, decode_time);
+                if let Some(writer) = recorder.lock().as_mut() {
+                    writer.write(&frame, image.as_ref());
                 }
+                sequence += 1;
+                let frame = Arc::new(Frame {
+                    sequence,
+                    captured_at,
+                    timestamp,
+                    image,
+                    raw: options.keep_raw.then_some(frame),
+                });
+                last_frame.put(Arc::clone(&frame));
+                if handlers.dispatch(&frame) {
+                    last_frame.taken(frame.sequence);
+                }
+            }
+            last_frame.close();
+            handlers.stopped();
+            // Wakes up a set_format caller waiting for a switch which won't happen
+            pending_format.lock().take();
+            let result = camera.lock().stop_stream();
+            if disconnected {
+                // Closing the stream of a camera which is gone is expected to fail
+                return Ok(());
             }
+            *state.lock() = StreamState::Closed;
+            result
         });
-        Ok(())
+        *self.thread.lock() = Some(thread);
+        match opened_rx.recv() {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(err)) => {
+                let _ = self.stop();
+                Err(err)
+            }
+            Err(_) => {
+                let _ = self.stop();
+                Err(nokhwa::NokhwaError::GeneralError(
+                    "Capture thread panicked".to_string(),
+                ))
+            }
+        }
+    }
+    /// Stops the capture thread, waits for it to exit and closes the stream.
+    fn stop(&self) -> Result<(), nokhwa::NokhwaError> {
+        let Some(thread) = self.thread.lock().take() else {
+            return Ok(());
+        };
+        self.active.store(false, atomic::Ordering::Relaxed);
+        let result = match thread.join() {
+            Ok(result) => result,
+            Err(_) => Err(nokhwa::NokhwaError::GeneralError(
+                "Capture thread panicked".to_string(),
+            )),
+        };
+        *self.state.lock() = StreamState::Closed;
+        result
     }
-    fn last_frame(&self) -> Arc<Option<ImageBuffer<Rgb<u8>, Vec<u8>>>> {
-        Arc::clone(&self.last_frame.lock())
+    fn is_capturing(&self) -> bool {
+        self.thread
+            .lock()
+            .as_ref()
+            .is_some_and(|thread| !thread.is_finished())
+    }
+    /// Asks the capture thread to switch to another format. With *wait*, blocks until it's done
+    /// and returns its result, otherwise errors are reported through format_err.
+    fn set_format(&self, py: Python, format: CameraFormat, wait: bool) -> PyResult<()> {
+        if !self.is_capturing() {
+            return Err(StreamClosedError::new_err("Camera is not open"));
+        }
+        if !wait {
+            *self.pending_format.lock() = Some(FormatSwitch { format, done: None });
+            return Ok(());
+        }
+        let (done_tx, done_rx) = mpsc::sync_channel(1);
+        *self.pending_format.lock() = Some(FormatSwitch {
+            format,
+            done: Some(done_tx),
+        });
+        py.allow_threads(move || loop {
+            match done_rx.recv_timeout(Duration::from_millis(100)) {
+                Ok(result) => return result.map_err(|error| errors::to_py(&error)),
+                Err(mpsc::RecvTimeoutError::Timeout) if self.is_capturing() => {}
+                Err(_) => {
+                    return Err(StreamClosedError::new_err(
+                        "Capture stopped before the format was switched",
+                    ))
+                }
+            }
+        })
+    }
+    fn last_frame(&self) -> Option<Arc<Frame>> {
+        self.last_frame.get()
+    }
+    fn wait_frame(&self, after: u64, timeout: Option<Duration>) -> Option<Arc<Frame>> {
+        self.last_frame.wait_newer(after, timeout)
     }
 }
 
 impl Drop for CameraInternal {
     fn drop(&mut self) {
-        self.active.store(false, atomic::Ordering::Relaxed);
+        self.handlers.clear();
+        // Capture may be blocked on a python frame callback, which needs the GIL to finish
+        let _ = Python::with_gil(|py| py.allow_threads(|| self.stop()));
+        if let Some(writer) = self.recorder.lock().take() {
+            let _ = writer.finish();
+        }
+    }
+}
+
+fn frame_format_name(format: FrameFormat) -> &'static str {
+    match format {
+        FrameFormat::MJPEG => "mjpeg",
+        FrameFormat::YUYV => "yuyv",
+        FrameFormat::GRAY => "gray",
+        FrameFormat::NV12 => "nv12",
+        FrameFormat::RAWRGB => "rawrgb",
     }
 }
 
@@ -126,13 +479,7 @@ struct CamFormat {
 impl CamFormat {
     #[getter]
     fn get_format(&self) -> String {
-        match self.format {
-            FrameFormat::MJPEG => "mjpeg".to_string(),
-            FrameFormat::YUYV => "yuyv".to_string(),
-            FrameFormat::GRAY => "gray".to_string(),
-            FrameFormat::NV12 => "nv12".to_string(),
-            FrameFormat::RAWRGB => "rawrgb".to_string(),
-        }
+        frame_format_name(self.format).to_string()
     }
     //#[setter]
     fn set_format(&mut self, fmt: String) -> PyResult<()> {
@@ -166,76 +513,177 @@ impl From<CameraFormat> for CamFormat {
     }
 }
 
+fn parse_timeout(timeout: Option<f64>) -> PyResult<Option<Duration>> {
+    timeout
+        .map(|timeout| {
+            Duration::try_from_secs_f64(timeout)
+                .map_err(|error| PyValueError::new_err(error.to_string()))
+        })
+        .transpose()
+}
+
 #[pyclass]
-struct CamControl {
-    cam: Weak<FairMutex<nokhwa::Camera>>,
-    control: Mutex<CameraControl>,
+pub struct Camera {
+    cam: CameraInternal,
+    /// Sequence number of the last frame handed out by poll_frame/wait_frame.
+    last_seen: atomic::AtomicU64,
 }
 
-#[pymethods]
-impl CamControl {
-    fn value_range(&self) -> (i64, i64, i64) {
-        let control = self.control.lock().unwrap();
-        let control_desc = control.description();
-        match control_desc {
-            ControlValueDescription::IntegerRange { min, max, step, .. } => (*min, *max, *step),
-            _ => todo!(),
+impl Camera {
+    /// Passes every captured frame to *handler*, on a thread of its own. Up to *queue_size* frames wait
+    /// while it's busy, see Backpressure for what happens to further ones. Returns an id for remove_frame_handler.
+    pub fn add_frame_handler(
+        &self,
+        handler: Box<dyn FrameHandler>,
+        backpressure: Backpressure,
+        queue_size: usize,
+    ) -> u64 {
+        self.cam.handlers.add(handler, backpressure, queue_size)
+    }
+    /// Stops passing frames to a handler added by add_frame_handler or add_frame_callback,
+    /// and waits for it to finish the queued ones. Returns false if there's no handler with this id.
+    pub fn remove_frame_handler(&self, id: u64) -> bool {
+        self.cam.handlers.remove(id)
+    }
+    fn from_source(source: Box<dyn FrameSource>) -> Camera {
+        Camera {
+            cam: CameraInternal::new(source),
+            last_seen: atomic::AtomicU64::new(0),
         }
     }
-    #[pyo3(signature = (value=None))]
-    fn set_value(&self, value: Option<i64>) -> PyResult<()> {
-        let mut control = self.control.lock().unwrap();
-        match self.cam.upgrade() {
-            Some(cam) => match value {
-                Some(value) => {
-                    control.set_active(true);
-                    let mut cam = cam.lock();
-                    match cam.set_camera_control(
-                        control.control(),
-                        nokhwa::utils::ControlValueSetter::Integer(value),
-                    ) {
-                        Ok(_) => Ok(()),
-                        Err(error) => Err(PyRuntimeError::new_err(error.to_string())),
-                    }
-                }
-                None => {
-                    control.set_active(false);
-                    Ok(())
-                }
-            },
-            None => Err(PyRuntimeError::new_err(
-                "Control is unusable as camera object has been dropped".to_string(),
-            )),
+    /// Capture waits for blocking frame callbacks, so these can't wait for capture in turn.
+    fn check_not_blocking_handler(&self, action: &str) -> PyResult<()> {
+        if self.cam.handlers.on_blocking_handler() {
+            return Err(CameraError::new_err(format!(
+                "Can't {action} from a blocking frame callback, capture is waiting for it"
+            )));
         }
+        Ok(())
+    }
+    /// Reading the queue only makes sense if it's enabled and frames are coming in (or left over).
+    fn check_queue(&self) -> PyResult<()> {
+        if self.cam.last_frame.queued() == 0 {
+            // Frames captured before an error are handed out before it's raised
+            self.check_err(false)?;
+            if !self.cam.is_capturing() {
+                return Err(StreamClosedError::new_err("Camera is not open"));
+            }
+        }
+        if self.cam.last_frame.queue_size() == 0 {
+            return Err(PyValueError::new_err(
+                "Frame queue is disabled, open the camera with a non-zero queue_size",
+            ));
+        }
+        Ok(())
+    }
+    fn hand_out(&self, frame: Arc<Frame>) -> Option<CamFrame> {
+        self.last_seen
+            .fetch_max(frame.sequence, atomic::Ordering::Relaxed);
+        if frame.image.is_none() && frame.raw.is_none() {
+            return None;
+        }
+        Some(CamFrame { frame })
     }
-}
-
-#[pyclass]
-struct Camera {
-    cam: CameraInternal,
 }
 
 #[pymethods]
 impl Camera {
+    /// Open a camera by index, or by a DeviceInfo from query or find_device (which finds a camera again after its index changed).
+    /// Strings can be a device path (/dev/video0), a stable id, a camera name or a backend specific identifier.
+    /// *backend* is one of the names returned by backends(), cameras given by DeviceInfo use the one which listed them.
     #[new]
-    fn new(index: u32) -> PyResult<Camera> {
-        match nokhwa::Camera::new(
-            CameraIndex::Index(index),
-            RequestedFormat::new::<RgbFormat>(RequestedFormatType::None),
-        ) {
-            Ok(cam) => Ok(Camera {
-                cam: CameraInternal::new(cam),
-            }),
-            Err(error) => Err(PyRuntimeError::new_err(error.to_string())),
+    #[pyo3(signature = (index, backend="auto"))]
+    fn new(index: &Bound<'_, PyAny>, backend: &str) -> PyResult<Camera> {
+        let (index, backend) = camera_index(index, backend)?;
+        match source::open_source(index, backend) {
+            Ok(cam) => Ok(Camera::from_source(cam)),
+            Err(error) => Err(errors::to_py(&error)),
         }
     }
-    fn open(&self, format: CamFormat) -> PyResult<()> {
-        if let Err(error) = self.cam.start(format.into()) {
-            return Err(PyRuntimeError::new_err(error.to_string()));
+    /// Replay recorded footage: a directory of PNG/JPEG frames, an MJPEG stream,
+    /// or a raw rgb dump (in which case *resolution* has to be specified).
+    #[staticmethod]
+    #[pyo3(signature = (path, looping=false, frame_rate=30, resolution=None))]
+    fn from_file(
+        path: PathBuf,
+        looping: bool,
+        frame_rate: u32,
+        resolution: Option<(u32, u32)>,
+    ) -> PyResult<Camera> {
+        match playback::Playback::open(&path, looping, frame_rate, resolution) {
+            Ok(playback) => Ok(Camera::from_source(Box::new(playback))),
+            Err(error) => Err(errors::to_py(&error)),
         }
-        let has_captured = Arc::new(atomic::AtomicBool::new(false));
-        let _has_captured_clone = Arc::clone(&has_captured);
-        Ok(())
+    }
+    /// Start capturing frames in *format*. Raises if the camera can't be opened in it.
+    /// Frames are decoded to *pixel_format*, one of 'rgb', 'bgr', 'rgba', 'luma', 'i420'.
+    /// With *keep_raw*, frames also carry the data as it was received from the camera,
+    /// and *decode* can be turned off to skip decoding entirely. Raises ValueError if *decode*
+    /// is off without *keep_raw*, as frames wouldn't carry any data.
+    /// With a non-zero *queue_size*, every frame is also queued for read_frames/next_frame.
+    /// After *max_errors* failed reads in a row (or once the device is gone) the camera is considered
+    /// disconnected, capture either stops or, if *reconnect_interval* (in seconds) is given,
+    /// the camera is reopened with the same format and controls as soon as it's back.
+    #[pyo3(signature = (format, keep_raw=false, decode=true, pixel_format="rgb", queue_size=0, max_errors=30, reconnect_interval=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn open(
+        &self,
+        py: Python,
+        format: CamFormat,
+        keep_raw: bool,
+        decode: bool,
+        pixel_format: &str,
+        queue_size: usize,
+        max_errors: u32,
+        reconnect_interval: Option<f64>,
+    ) -> PyResult<()> {
+        let Some(pixel_format) = PixelFormat::from_name(pixel_format) else {
+            return Err(PyValueError::new_err(format!(
+                "Unsupported pixel format (should be one of {})",
+                PixelFormat::NAMES
+            )));
+        };
+        self.check_not_blocking_handler("open the camera")?;
+        if !decode && !keep_raw {
+            return Err(PyValueError::new_err(
+                "Frames would carry no data, keep_raw is required if decode is false",
+            ));
+        }
+        let options = CaptureOptions {
+            keep_raw,
+            decode,
+            pixel_format,
+            queue_size,
+            max_errors: max_errors.max(1),
+            reconnect_interval: parse_timeout(reconnect_interval)?,
+        };
+        // Reopening joins the capture thread, which may be waiting for a frame callback that needs the GIL
+        py.allow_threads(|| self.cam.start(format.into(), options))
+            .map_err(|error| errors::to_py(&error))
+    }
+
+    /// Switch an open camera to another format without restarting capture.
+    /// With *wait*, blocks until the switch is done and raises if it failed,
+    /// otherwise failures are reported by check_err. Either way the previous format stays in use on failure.
+    #[pyo3(signature = (format, wait=false))]
+    fn set_format(&self, py: Python, format: CamFormat, wait: bool) -> PyResult<()> {
+        if wait {
+            self.check_not_blocking_handler("wait for a format switch")?;
+        }
+        self.cam.set_format(py, format.into(), wait)
+    }
+
+    /// Stop capturing and close the stream. The camera can be opened again afterwards,
+    /// possibly with a different format.
+    fn close(&self, py: Python) -> PyResult<()> {
+        self.check_not_blocking_handler("close the camera")?;
+        py.allow_threads(|| self.cam.stop())
+            .map_err(|error| errors::to_py(&error))
+    }
+
+    /// One of 'closed', 'streaming', 'disconnected', 'reconnecting'.
+    fn state(&self) -> &'static str {
+        self.cam.state.lock().name()
     }
 
     fn info(&self) -> PyResult<String> {
@@ -245,30 +693,154 @@ impl Camera {
         ))
     }
 
+    /// Currently selected format.
+    fn get_format(&self) -> CamFormat {
+        self.cam.camera.lock().camera_format().into()
+    }
+
     fn get_formats(&self) -> PyResult<Vec<CamFormat>> {
         match self.cam.camera.lock().compatible_camera_formats() {
             Ok(formats) => Ok(formats.into_iter().map(|x| x.into()).collect()),
-            Err(error) => Err(PyRuntimeError::new_err(error.to_string())),
+            Err(error) => Err(errors::to_py(&error)),
         }
     }
 
-    fn poll_frame(&self, py: Python) -> PyResult<Option<(u32, u32, Py<PyBytes>)>> {
-        match &*self.cam.last_frame() {
-            Some(frame) => Ok(Some((
-                frame.width(),
-                frame.height(),
-                PyBytes::new(py, frame).into(),
-            ))),
-            None => Ok(None),
+    fn poll_frame(&self) -> PyResult<Option<CamFrame>> {
+        Ok(self.cam.last_frame().and_then(|frame| self.hand_out(frame)))
+    }
+
+    /// Block until a frame newer than the last one returned by poll_frame/wait_frame arrives.
+    /// Returns None if *timeout* (in seconds) expires or the capture thread has stopped.
+    #[pyo3(signature = (timeout=None))]
+    fn wait_frame(&self, py: Python, timeout: Option<f64>) -> PyResult<Option<CamFrame>> {
+        let timeout = parse_timeout(timeout)?;
+        let after = self.last_seen.load(atomic::Ordering::Relaxed);
+        Ok(py
+            .allow_threads(|| self.cam.wait_frame(after, timeout))
+            .and_then(|frame| self.hand_out(frame)))
+    }
+
+    /// Return all queued frames, oldest first. Requires a non-zero queue_size.
+    /// Once no frames are left, raises the error which stopped capture (see check_err),
+    /// or StreamClosedError if the camera isn't open.
+    fn read_frames(&self) -> PyResult<Vec<CamFrame>> {
+        self.check_queue()?;
+        Ok(self
+            .cam
+            .last_frame
+            .drain()
+            .into_iter()
+            .filter_map(|frame| self.hand_out(frame))
+            .collect())
+    }
+
+    /// Remove the oldest frame from the queue, waiting for one if the queue is empty.
+    /// Returns None if *timeout* (in seconds) expires or the capture thread has stopped.
+    /// Raises like read_frames once no frames are left.
+    #[pyo3(signature = (timeout=None))]
+    fn next_frame(&self, py: Python, timeout: Option<f64>) -> PyResult<Option<CamFrame>> {
+        let timeout = parse_timeout(timeout)?;
+        self.check_queue()?;
+        match py.allow_threads(|| self.cam.last_frame.pop(timeout)) {
+            Some(frame) => Ok(self.hand_out(frame)),
+            None => self.check_err(false).map(|()| None),
+        }
+    }
+
+    /// Frame rate, timings and counters of delivered, dropped and failed frames since the camera was opened.
+    fn stats(&self) -> CaptureStats {
+        self.cam.stats.lock().snapshot(
+            self.cam.last_frame.dropped(),
+            self.cam.last_frame.overwritten(),
+        )
+    }
+
+    /// Call *callback* with every new frame, from a thread of its own.
+    /// Up to *queue_size* frames wait while the callback is busy, further ones are skipped,
+    /// or with *block*, capture waits for the callback to catch up (so the callback can't close
+    /// or reopen the camera, that raises CameraError).
+    /// *on_stop* is called (without arguments) after the last frame when capture stops.
+    /// Exceptions raised by the callbacks are printed, as there's no caller to raise them to.
+    /// Returns an id for remove_frame_callback.
+    #[pyo3(signature = (callback, block=false, queue_size=4, on_stop=None))]
+    fn add_frame_callback(
+        &self,
+        callback: Py<PyAny>,
+        block: bool,
+        queue_size: usize,
+        on_stop: Option<Py<PyAny>>,
+    ) -> u64 {
+        let backpressure = if block {
+            Backpressure::Block
+        } else {
+            Backpressure::Drop
+        };
+        self.add_frame_handler(
+            Box::new(PyFrameHandler::new(callback, on_stop)),
+            backpressure,
+            queue_size,
+        )
+    }
+
+    /// Stop calling a callback added by add_frame_callback, waits for it to finish queued frames.
+    fn remove_frame_callback(&self, py: Python, id: u64) -> PyResult<()> {
+        if py.allow_threads(|| self.remove_frame_handler(id)) {
+            Ok(())
+        } else {
+            Err(PyKeyError::new_err(id))
+        }
+    }
+
+    /// Number of frames evicted from a full queue before being read, since the camera was opened.
+    fn dropped_frames(&self) -> u64 {
+        self.cam.last_frame.dropped()
+    }
+
+    /// Start writing captured frames to an MJPEG AVI file at *path*.
+    /// MJPEG frames are stored without re-encoding.
+    fn start_recording(&self, path: PathBuf) -> PyResult<()> {
+        let mut recorder = self.cam.recorder.lock();
+        if recorder.is_some() {
+            return Err(CameraError::new_err("Already recording"));
+        }
+        let frame_rate = self.cam.camera.lock().camera_format().frame_rate();
+        match AviWriter::create(&path, frame_rate) {
+            Ok(writer) => {
+                *recorder = Some(writer);
+                Ok(())
+            }
+            Err(error) => Err(CameraError::new_err(error.to_string())),
         }
     }
 
-    fn check_err(&self) -> PyResult<()> {
-        match &*self.cam.last_err.lock() {
-            Some(error) => Err(PyRuntimeError::new_err(error.to_string())),
-            None => Ok(()),
+    /// Finish the recording started by start_recording.
+    fn stop_recording(&self) -> PyResult<()> {
+        match self.cam.recorder.lock().take() {
+            Some(writer) => writer
+                .finish()
+                .map_err(|error| CameraError::new_err(error.to_string())),
+            None => Err(CameraError::new_err("Not recording")),
         }
     }
+
+    /// Raise the error which stopped capture, if any, or (once) the error of a failed format switch.
+    /// Frames which failed to read or decode don't stop capture, they're counted by stats(),
+    /// and with *frame_errors* the most recent of these failures is raised (once) as well.
+    #[pyo3(signature = (frame_errors=false))]
+    fn check_err(&self, frame_errors: bool) -> PyResult<()> {
+        if let Some(error) = &*self.cam.last_err.lock() {
+            return Err(errors::to_py(error));
+        }
+        if let Some(error) = self.cam.format_err.lock().take() {
+            return Err(errors::to_py(&error));
+        }
+        if frame_errors {
+            if let Some(error) = self.cam.stats.lock().take_frame_error() {
+                return Err(errors::to_py(&error));
+            }
+        }
+        Ok(())
+    }
     fn get_controls(&self) -> PyResult<Vec<(String, CamControl)>> {
         match self.cam.camera.lock().camera_controls_string() {
             Ok(list) => Ok(list
@@ -283,9 +855,163 @@ impl Camera {
                     )
                 })
                 .collect()),
-            Err(_err) => {
-                Ok(Vec::new()) // Nothing supported
+            Err(error) => Err(errors::to_py(&error)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_pattern::{Pattern, TestPattern};
+
+    use super::*;
+
+    const TIMEOUT: Option<Duration> = Some(Duration::from_secs(5));
+
+    fn options() -> CaptureOptions {
+        CaptureOptions {
+            keep_raw: false,
+            decode: true,
+            pixel_format: PixelFormat::Rgb,
+            queue_size: 0,
+            max_errors: 30,
+            reconnect_interval: None,
+        }
+    }
+
+    fn test_pattern() -> CameraInternal {
+        // Dropping a camera takes the GIL
+        pyo3::prepare_freethreaded_python();
+        CameraInternal::new(Box::new(TestPattern::new(Pattern::ColorBars)))
+    }
+
+    #[test]
+    fn captures_test_pattern() {
+        let cam = test_pattern();
+        let format = CameraFormat::new_from(320, 240, FrameFormat::YUYV, 60);
+        cam.start(format, options()).unwrap();
+        assert_eq!(cam.state.lock().name(), "streaming");
+        let first = cam.wait_frame(0, TIMEOUT).unwrap();
+        let second = cam.wait_frame(first.sequence, TIMEOUT).unwrap();
+        assert!(second.sequence > first.sequence);
+        let image = second.image.as_ref().unwrap();
+        assert_eq!((image.width, image.height), (320, 240));
+        assert_eq!(image.data.len(), 320 * 240 * 3);
+        cam.stop().unwrap();
+        assert_eq!(cam.state.lock().name(), "closed");
+        assert!(cam.last_err.lock().is_none());
+        let last = cam.last_frame.last_sequence();
+        assert!(cam.wait_frame(last, TIMEOUT).is_none());
+    }
+
+    #[test]
+    fn start_fails_with_unsupported_format() {
+        let cam = test_pattern();
+        let format = CameraFormat::new_from(123, 45, FrameFormat::YUYV, 60);
+        assert!(cam.start(format, options()).is_err());
+        assert!(!cam.is_capturing());
+        assert_eq!(cam.state.lock().name(), "closed");
+    }
+
+    #[test]
+    fn switches_format_while_capturing() {
+        let cam = test_pattern();
+        let format = CameraFormat::new_from(320, 240, FrameFormat::YUYV, 60);
+        cam.start(format, options()).unwrap();
+        let larger = CameraFormat::new_from(640, 480, FrameFormat::YUYV, 60);
+        Python::with_gil(|py| cam.set_format(py, larger, true)).unwrap();
+        let unsupported = CameraFormat::new_from(123, 45, FrameFormat::YUYV, 60);
+        assert!(Python::with_gil(|py| cam.set_format(py, unsupported, true)).is_err());
+        // The previous format stays in use
+        let after = cam.last_frame.last_sequence();
+        let frame = cam.wait_frame(after, TIMEOUT).unwrap();
+        let image = frame.image.as_ref().unwrap();
+        assert_eq!((image.width, image.height), (640, 480));
+        assert!(cam.format_err.lock().is_none());
+    }
+
+    fn camera() -> Camera {
+        Camera {
+            cam: test_pattern(),
+            last_seen: atomic::AtomicU64::new(0),
+        }
+    }
+
+    #[test]
+    fn hands_out_queued_frames_before_raising() {
+        let camera = camera();
+        let format = CameraFormat::new_from(320, 240, FrameFormat::YUYV, 60);
+        let options = CaptureOptions {
+            queue_size: 8,
+            ..options()
+        };
+        camera.cam.start(format, options).unwrap();
+        camera.cam.wait_frame(1, TIMEOUT).unwrap();
+        camera.cam.stop().unwrap();
+        *camera.cam.last_err.lock() =
+            Some(nokhwa::NokhwaError::ReadFrameError("broken".to_string()));
+        Python::with_gil(|py| {
+            assert!(camera.next_frame(py, Some(5.0)).unwrap().is_some());
+            assert!(!camera.read_frames().unwrap().is_empty());
+            assert!(camera.read_frames().is_err());
+            assert!(camera.next_frame(py, Some(5.0)).is_err());
+        });
+    }
+
+    #[test]
+    fn raises_frame_errors_once_on_request() {
+        let camera = camera();
+        let error = nokhwa::NokhwaError::ReadFrameError("broken".to_string());
+        camera.cam.stats.lock().read_failed(&error);
+        assert!(camera.check_err(false).is_ok());
+        assert!(camera.check_err(true).is_err());
+        assert!(camera.check_err(true).is_ok());
+    }
+
+    /// Tries to close the camera it's registered on, reporting whether that raised.
+    struct CloseOnFrame {
+        camera: std::sync::Weak<Camera>,
+        raised: mpsc::Sender<bool>,
+    }
+
+    impl FrameHandler for CloseOnFrame {
+        fn on_frame(&mut self, _frame: &Arc<Frame>) {
+            if let Some(camera) = self.camera.upgrade() {
+                let raised = Python::with_gil(|py| camera.close(py).is_err());
+                let _ = self.raised.send(raised);
             }
         }
     }
+
+    #[test]
+    fn blocking_handler_cannot_close_camera() {
+        let camera = Arc::new(camera());
+        let (raised_tx, raised_rx) = mpsc::channel();
+        let handler = CloseOnFrame {
+            camera: Arc::downgrade(&camera),
+            raised: raised_tx,
+        };
+        camera.add_frame_handler(Box::new(handler), Backpressure::Block, 1);
+        let format = CameraFormat::new_from(320, 240, FrameFormat::YUYV, 60);
+        camera.cam.start(format, options()).unwrap();
+        assert!(raised_rx.recv_timeout(TIMEOUT.unwrap()).unwrap());
+        assert!(camera.cam.is_capturing());
+        camera.cam.stop().unwrap();
+    }
+
+    #[test]
+    fn queues_frames() {
+        let cam = test_pattern();
+        let format = CameraFormat::new_from(320, 240, FrameFormat::YUYV, 60);
+        let options = CaptureOptions {
+            queue_size: 8,
+            ..options()
+        };
+        cam.start(format, options).unwrap();
+        let first = cam.last_frame.pop(TIMEOUT).unwrap();
+        let second = cam.last_frame.pop(TIMEOUT).unwrap();
+        assert_eq!(second.sequence, first.sequence + 1);
+        cam.stop().unwrap();
+        assert_eq!(cam.last_frame.overwritten(), 0);
+    }
 }
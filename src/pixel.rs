@@ -0,0 +1,255 @@
+use std::borrow::Cow;
+
+use image::ColorType;
+use nokhwa::{
+    pixel_format::{LumaFormat, RgbAFormat, RgbFormat},
+    Buffer, NokhwaError,
+};
+
+/// Layout of decoded frames.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    Rgb,
+    Bgr,
+    Rgba,
+    Luma,
+    /// Planar YUV 4:2:0: full resolution Y plane followed by quarter resolution U and V planes.
+    I420,
+}
+
+impl PixelFormat {
+    pub(crate) const NAMES: &'static str = "'rgb', 'bgr', 'rgba', 'luma', 'i420'";
+
+    pub(crate) fn from_name(name: &str) -> Option<PixelFormat> {
+        match name {
+            "rgb" => Some(PixelFormat::Rgb),
+            "bgr" => Some(PixelFormat::Bgr),
+            "rgba" => Some(PixelFormat::Rgba),
+            "luma" => Some(PixelFormat::Luma),
+            "i420" => Some(PixelFormat::I420),
+            _ => None,
+        }
+    }
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            PixelFormat::Rgb => "rgb",
+            PixelFormat::Bgr => "bgr",
+            PixelFormat::Rgba => "rgba",
+            PixelFormat::Luma => "luma",
+            PixelFormat::I420 => "i420",
+        }
+    }
+}
+
+/// A decoded frame.
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+    pub data: Vec<u8>,
+}
+
+impl Image {
+    /// Dimensions of data when viewed as an array.
+    /// I420 frames are viewed as a (height * 3 / 2, width) array when possible, like OpenCV does.
+    pub(crate) fn shape(&self) -> Vec<usize> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        match self.format {
+            PixelFormat::Rgb | PixelFormat::Bgr => vec![height, width, 3],
+            PixelFormat::Rgba => vec![height, width, 4],
+            PixelFormat::Luma => vec![height, width],
+            PixelFormat::I420 if width % 2 == 0 && height % 2 == 0 => vec![height * 3 / 2, width],
+            PixelFormat::I420 => vec![self.data.len()],
+        }
+    }
+    /// Data and color type suitable for image encoders. Only BGR and I420 need to be converted.
+    pub(crate) fn encodable(&self) -> (Cow<'_, [u8]>, ColorType) {
+        match self.format {
+            PixelFormat::Rgb => (Cow::Borrowed(&self.data), ColorType::Rgb8),
+            PixelFormat::Rgba => (Cow::Borrowed(&self.data), ColorType::Rgba8),
+            PixelFormat::Luma => (Cow::Borrowed(&self.data), ColorType::L8),
+            PixelFormat::Bgr => {
+                let mut rgb = self.data.clone();
+                swap_red_blue(&mut rgb);
+                (Cow::Owned(rgb), ColorType::Rgb8)
+            }
+            PixelFormat::I420 => (
+                Cow::Owned(i420_to_rgb(&self.data, self.width, self.height)),
+                ColorType::Rgb8,
+            ),
+        }
+    }
+}
+
+/// Decodes a frame received from the camera straight to the requested layout.
+pub(crate) fn decode(buffer: &Buffer, format: PixelFormat) -> Result<Image, NokhwaError> {
+    let resolution = buffer.resolution();
+    let (width, height) = (resolution.width(), resolution.height());
+    let data = match format {
+        PixelFormat::Rgb => buffer.decode_image::<RgbFormat>()?.into_raw(),
+        PixelFormat::Rgba => buffer.decode_image::<RgbAFormat>()?.into_raw(),
+        PixelFormat::Luma => buffer.decode_image::<LumaFormat>()?.into_raw(),
+        PixelFormat::Bgr => {
+            let mut data = buffer.decode_image::<RgbFormat>()?.into_raw();
+            swap_red_blue(&mut data);
+            data
+        }
+        PixelFormat::I420 => {
+            let rgb = buffer.decode_image::<RgbFormat>()?.into_raw();
+            rgb_to_i420(&rgb, width, height)
+        }
+    };
+    Ok(Image {
+        width,
+        height,
+        format,
+        data,
+    })
+}
+
+fn swap_red_blue(data: &mut [u8]) {
+    for pixel in data.chunks_exact_mut(3) {
+        pixel.swap(0, 2);
+    }
+}
+
+fn rgb_to_yuv(r: i32, g: i32, b: i32) -> (u8, u8, u8) {
+    let y = ((66 * r + 129 * g + 25 * b + 128) >> 8) + 16;
+    let u = ((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128;
+    let v = ((112 * r - 94 * g - 18 * b + 128) >> 8) + 128;
+    (
+        y.clamp(0, 255) as u8,
+        u.clamp(0, 255) as u8,
+        v.clamp(0, 255) as u8,
+    )
+}
+
+fn yuv_to_rgb(y: u8, u: u8, v: u8) -> [u8; 3] {
+    let c = (y as i32 - 16) * 298;
+    let d = u as i32 - 128;
+    let e = v as i32 - 128;
+    let clamp = |value: i32| ((value + 128) >> 8).clamp(0, 255) as u8;
+    [
+        clamp(c + 409 * e),
+        clamp(c - 100 * d - 208 * e),
+        clamp(c + 516 * d),
+    ]
+}
+
+/// Converts packed rgb to YUYV 4:2:2 using BT.601 coefficients. Width must be even.
+pub(crate) fn rgb_to_yuyv(rgb: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(rgb.len() / 3 * 2);
+    for pair in rgb.chunks_exact(6) {
+        let (r0, g0, b0) = (pair[0] as i32, pair[1] as i32, pair[2] as i32);
+        let (r1, g1, b1) = (pair[3] as i32, pair[4] as i32, pair[5] as i32);
+        let (y0, _, _) = rgb_to_yuv(r0, g0, b0);
+        let (y1, _, _) = rgb_to_yuv(r1, g1, b1);
+        let (_, u, v) = rgb_to_yuv((r0 + r1) / 2, (g0 + g1) / 2, (b0 + b1) / 2);
+        out.extend_from_slice(&[y0, u, y1, v]);
+    }
+    out
+}
+
+/// Converts packed rgb to planar I420, chroma is averaged over 2x2 blocks.
+fn rgb_to_i420(rgb: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+    let mut out = vec![0; width * height + 2 * chroma_width * chroma_height];
+    let (luma, chroma) = out.split_at_mut(width * height);
+    let (u_plane, v_plane) = chroma.split_at_mut(chroma_width * chroma_height);
+    for y in 0..height {
+        for x in 0..width {
+            let offset = (y * width + x) * 3;
+            let (r, g, b) = (rgb[offset], rgb[offset + 1], rgb[offset + 2]);
+            luma[y * width + x] = rgb_to_yuv(r as i32, g as i32, b as i32).0;
+        }
+    }
+    for cy in 0..chroma_height {
+        for cx in 0..chroma_width {
+            let (mut r, mut g, mut b, mut count) = (0, 0, 0, 0);
+            for y in cy * 2..(cy * 2 + 2).min(height) {
+                for x in cx * 2..(cx * 2 + 2).min(width) {
+                    let offset = (y * width + x) * 3;
+                    r += rgb[offset] as i32;
+                    g += rgb[offset + 1] as i32;
+                    b += rgb[offset + 2] as i32;
+                    count += 1;
+                }
+            }
+            let (_, u, v) = rgb_to_yuv(r / count, g / count, b / count);
+            u_plane[cy * chroma_width + cx] = u;
+            v_plane[cy * chroma_width + cx] = v;
+        }
+    }
+    out
+}
+
+fn i420_to_rgb(i420: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let chroma_width = width.div_ceil(2);
+    let (luma, chroma) = i420.split_at(width * height);
+    let (u_plane, v_plane) = chroma.split_at(chroma.len() / 2);
+    let mut rgb = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        for x in 0..width {
+            let chroma_offset = (y / 2) * chroma_width + x / 2;
+            rgb.extend_from_slice(&yuv_to_rgb(
+                luma[y * width + x],
+                u_plane[chroma_offset],
+                v_plane[chroma_offset],
+            ));
+        }
+    }
+    rgb
+}
+
+#[cfg(test)]
+mod tests {
+    use nokhwa::utils::{FrameFormat, Resolution};
+
+    use super::*;
+
+    fn assert_close(actual: &[u8], expected: &[u8], tolerance: u8) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!(a.abs_diff(*e) <= tolerance, "{actual:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn i420_round_trip() {
+        let colors = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [128, 128, 128]];
+        for color in colors {
+            let rgb: Vec<u8> = color.repeat(4 * 2);
+            let i420 = rgb_to_i420(&rgb, 4, 2);
+            assert_eq!(i420.len(), 4 * 2 + 2 * 2);
+            assert_close(&i420_to_rgb(&i420, 4, 2), &rgb, 3);
+        }
+    }
+
+    #[test]
+    fn i420_odd_dimensions() {
+        let rgb = [200, 100, 50].repeat(3 * 3);
+        let i420 = rgb_to_i420(&rgb, 3, 3);
+        // Chroma planes are rounded up to 2x2
+        assert_eq!(i420.len(), 9 + 2 * 4);
+        assert_close(&i420_to_rgb(&i420, 3, 3), &rgb, 3);
+    }
+
+    #[test]
+    fn i420_averages_chroma() {
+        let rgb = [[255, 0, 0], [0, 0, 255]].concat().repeat(2);
+        let i420 = rgb_to_i420(&rgb, 2, 2);
+        let (_, u, v) = rgb_to_yuv(127, 0, 127);
+        assert_eq!(&i420[4..], [u, v]);
+    }
+
+    #[test]
+    fn decodes_to_bgr() {
+        let buffer = Buffer::new(Resolution::new(1, 1), &[1, 2, 3], FrameFormat::RAWRGB);
+        let image = decode(&buffer, PixelFormat::Bgr).unwrap();
+        assert_eq!(image.data, [3, 2, 1]);
+        let (data, color_type) = image.encodable();
+        assert_eq!((&*data, color_type), (&[1, 2, 3][..], ColorType::Rgb8));
+    }
+}
@@ -0,0 +1,312 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use nokhwa::{
+    utils::{
+        CameraControl, CameraFormat, ControlValueSetter, FrameFormat, KnownCameraControl,
+        Resolution,
+    },
+    Buffer, NokhwaError,
+};
+
+use crate::source::{FramePacer, FrameSource};
+
+enum Input {
+    /// Back to back JPEG images, as produced by MJPEG cameras.
+    Mjpeg(BufReader<File>),
+    /// One PNG/JPEG file per frame, played in file name order.
+    Images { paths: Vec<PathBuf>, next: usize },
+    /// Back to back frames of packed rgb values.
+    RawRgb(BufReader<File>),
+}
+
+/// Replays recorded footage as if it was coming from a camera.
+pub(crate) struct Playback {
+    input: Input,
+    format: CameraFormat,
+    looping: bool,
+    stream_open: bool,
+    /// Set once every frame was played without looping, reopening the stream starts over.
+    ended: bool,
+    pacer: FramePacer,
+}
+
+fn open_error(path: &Path, error: impl ToString) -> NokhwaError {
+    NokhwaError::OpenDeviceError(path.display().to_string(), error.to_string())
+}
+
+fn read_error(error: impl ToString) -> NokhwaError {
+    NokhwaError::ReadFrameError(error.to_string())
+}
+
+/// Reads the next JPEG image (from SOI to EOI marker) from an MJPEG stream.
+/// Returns None at the end of the stream.
+fn read_jpeg(reader: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut jpeg = Vec::new();
+    let mut prev = 0;
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(None);
+        }
+        let mut end = None;
+        for (i, &byte) in buf.iter().enumerate() {
+            if jpeg.is_empty() {
+                if prev == 0xFF && byte == 0xD8 {
+                    jpeg.extend_from_slice(&[0xFF, 0xD8]);
+                }
+            } else {
+                jpeg.push(byte);
+                if prev == 0xFF && byte == 0xD9 {
+                    end = Some(i + 1);
+                    break;
+                }
+            }
+            prev = byte;
+        }
+        let consumed = end.unwrap_or(buf.len());
+        reader.consume(consumed);
+        if end.is_some() {
+            return Ok(Some(jpeg));
+        }
+    }
+}
+
+fn is_image_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase())
+        .is_some_and(|ext| ["png", "jpg", "jpeg"].contains(&ext.as_str()))
+}
+
+impl Playback {
+    /// Opens *path*, which can be a directory of PNG/JPEG frames, a raw rgb dump (requires
+    /// *resolution*) or an MJPEG stream.
+    pub(crate) fn open(
+        path: &Path,
+        looping: bool,
+        frame_rate: u32,
+        resolution: Option<(u32, u32)>,
+    ) -> Result<Playback, NokhwaError> {
+        let (input, (width, height), frame_format) = if path.is_dir() {
+            let mut paths: Vec<PathBuf> = path
+                .read_dir()
+                .map_err(|error| open_error(path, error))?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| is_image_file(path))
+                .collect();
+            paths.sort();
+            let first = paths
+                .first()
+                .ok_or_else(|| open_error(path, "Directory contains no PNG/JPEG files"))?;
+            let size = image::image_dimensions(first).map_err(|error| open_error(first, error))?;
+            (Input::Images { paths, next: 0 }, size, FrameFormat::RAWRGB)
+        } else {
+            let file = File::open(path).map_err(|error| open_error(path, error))?;
+            let mut reader = BufReader::new(file);
+            match resolution {
+                Some(size) => (Input::RawRgb(reader), size, FrameFormat::RAWRGB),
+                None => {
+                    let jpeg = read_jpeg(&mut reader)
+                        .map_err(|error| open_error(path, error))?
+                        .ok_or_else(|| {
+                            open_error(
+                                path,
+                                "No JPEG frames found, pass a resolution for raw rgb dumps",
+                            )
+                        })?;
+                    let size = image::io::Reader::new(Cursor::new(jpeg))
+                        .with_guessed_format()
+                        .map_err(|error| open_error(path, error))?
+                        .into_dimensions()
+                        .map_err(|error| open_error(path, error))?;
+                    reader
+                        .seek(SeekFrom::Start(0))
+                        .map_err(|error| open_error(path, error))?;
+                    (Input::Mjpeg(reader), size, FrameFormat::MJPEG)
+                }
+            }
+        };
+        Ok(Playback {
+            input,
+            format: CameraFormat::new_from(width, height, frame_format, frame_rate.max(1)),
+            looping,
+            stream_open: false,
+            ended: false,
+            pacer: FramePacer::new(),
+        })
+    }
+
+    fn rewind(&mut self) -> Result<(), NokhwaError> {
+        match &mut self.input {
+            Input::Mjpeg(reader) | Input::RawRgb(reader) => {
+                reader.seek(SeekFrom::Start(0)).map_err(read_error)?;
+            }
+            Input::Images { next, .. } => *next = 0,
+        }
+        Ok(())
+    }
+
+    /// Returns the data of the next frame, or None at the end of the recording.
+    fn read_frame(&mut self) -> Result<Option<Vec<u8>>, NokhwaError> {
+        let (width, height) = (self.format.width(), self.format.height());
+        match &mut self.input {
+            Input::Mjpeg(reader) => read_jpeg(reader).map_err(read_error),
+            Input::RawRgb(reader) => {
+                let mut data = vec![0; width as usize * height as usize * 3];
+                match reader.read_exact(&mut data) {
+                    Ok(()) => Ok(Some(data)),
+                    Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+                    Err(error) => Err(read_error(error)),
+                }
+            }
+            Input::Images { paths, next } => {
+                let Some(path) = paths.get(*next) else {
+                    return Ok(None);
+                };
+                *next += 1;
+                let image = image::open(path).map_err(read_error)?.to_rgb8();
+                if image.dimensions() != (width, height) {
+                    return Err(read_error(format!(
+                        "{} has a different resolution than the first frame",
+                        path.display()
+                    )));
+                }
+                Ok(Some(image.into_raw()))
+            }
+        }
+    }
+}
+
+impl FrameSource for Playback {
+    fn camera_format(&mut self) -> CameraFormat {
+        self.format
+    }
+    fn compatible_camera_formats(&mut self) -> Result<Vec<CameraFormat>, NokhwaError> {
+        Ok(vec![self.format])
+    }
+    /// Any frame rate is accepted, resolution and frame format are fixed by the recording.
+    fn set_camera_format(&mut self, format: CameraFormat) -> Result<(), NokhwaError> {
+        if format.resolution() != self.format.resolution()
+            || format.format() != self.format.format()
+            || format.frame_rate() == 0
+        {
+            return Err(NokhwaError::SetPropertyError {
+                property: "CameraFormat".to_string(),
+                value: format!("{format:?}"),
+                error: "Unsupported format".to_string(),
+            });
+        }
+        self.format = format;
+        Ok(())
+    }
+    fn camera_controls_string(&mut self) -> Result<HashMap<String, CameraControl>, NokhwaError> {
+        Ok(HashMap::new())
+    }
+    fn camera_control(&mut self, id: KnownCameraControl) -> Result<CameraControl, NokhwaError> {
+        Err(NokhwaError::GetPropertyError {
+            property: format!("{id:?}"),
+            error: "Playback has no controls".to_string(),
+        })
+    }
+    fn set_camera_control(
+        &mut self,
+        id: KnownCameraControl,
+        value: ControlValueSetter,
+    ) -> Result<(), NokhwaError> {
+        Err(NokhwaError::SetPropertyError {
+            property: format!("{id:?}"),
+            value: format!("{value:?}"),
+            error: "Playback has no controls".to_string(),
+        })
+    }
+    fn open_stream(&mut self) -> Result<(), NokhwaError> {
+        if self.ended {
+            self.rewind()?;
+            self.ended = false;
+        }
+        self.stream_open = true;
+        self.pacer.reset();
+        Ok(())
+    }
+    fn frame(&mut self) -> Result<Buffer, NokhwaError> {
+        if !self.stream_open {
+            return Err(read_error("Stream is not open"));
+        }
+        self.pacer.wait(self.format.frame_rate());
+        let mut data = self.read_frame()?;
+        if data.is_none() && self.looping {
+            self.rewind()?;
+            data = self.read_frame()?;
+        }
+        let Some(data) = data else {
+            self.ended = true;
+            return Err(read_error("End of recording"));
+        };
+        let resolution = Resolution::new(self.format.width(), self.format.height());
+        Ok(Buffer::new(resolution, &data, self.format.format()))
+    }
+    fn stop_stream(&mut self) -> Result<(), NokhwaError> {
+        self.stream_open = false;
+        Ok(())
+    }
+    fn at_end(&mut self) -> bool {
+        self.ended
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process};
+
+    use super::*;
+
+    #[test]
+    fn read_jpeg_splits_back_to_back_images() {
+        let stream = [
+            &[0x00, 0xFF][..],
+            &[0xFF, 0xD8, 1, 2, 0xFF, 0xD9],
+            &[0xFF, 0xD8, 0xFF, 0x00, 3, 0xFF, 0xD9],
+            &[0xFF, 0xD8, 4],
+        ]
+        .concat();
+        // A tiny buffer makes markers straddle buffer boundaries
+        let mut reader = BufReader::with_capacity(3, Cursor::new(stream));
+        assert_eq!(
+            read_jpeg(&mut reader).unwrap(),
+            Some(vec![0xFF, 0xD8, 1, 2, 0xFF, 0xD9])
+        );
+        assert_eq!(
+            read_jpeg(&mut reader).unwrap(),
+            Some(vec![0xFF, 0xD8, 0xFF, 0x00, 3, 0xFF, 0xD9])
+        );
+        // Truncated image at the end
+        assert_eq!(read_jpeg(&mut reader).unwrap(), None);
+        assert_eq!(read_jpeg(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn stops_at_the_end_of_a_recording() {
+        let path = std::env::temp_dir().join(format!("omni_camera_playback_{}.rgb", process::id()));
+        let frames: Vec<u8> = (0..2u8).flat_map(|i| [i; 2 * 2 * 3]).collect();
+        fs::write(&path, frames).unwrap();
+        let mut playback = Playback::open(&path, false, 1000, Some((2, 2))).unwrap();
+        playback.open_stream().unwrap();
+        assert_eq!(playback.frame().unwrap().buffer(), [0; 12]);
+        assert_eq!(playback.frame().unwrap().buffer(), [1; 12]);
+        assert!(!playback.at_end());
+        assert!(playback.frame().is_err());
+        assert!(playback.at_end());
+        // Reopening starts over
+        playback.stop_stream().unwrap();
+        playback.open_stream().unwrap();
+        assert!(!playback.at_end());
+        assert_eq!(playback.frame().unwrap().buffer(), [0; 12]);
+        drop(playback);
+        fs::remove_file(&path).unwrap();
+    }
+}
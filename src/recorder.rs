@@ -0,0 +1,267 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use image::codecs::jpeg::JpegEncoder;
+use nokhwa::{utils::FrameFormat, Buffer};
+
+use crate::pixel::Image;
+
+/// Size of everything before the first frame chunk: RIFF header, hdrl list and movi list header.
+const HEADER_SIZE: u32 = 224;
+const AVIF_HASINDEX: u32 = 0x10;
+const AVIIF_KEYFRAME: u32 = 0x10;
+const JPEG_QUALITY: u8 = 90;
+
+/// Writes frames to an MJPEG-in-AVI file.
+/// MJPEG frames are written as-is, other formats are encoded from the decoded image.
+pub(crate) struct AviWriter {
+    file: BufWriter<File>,
+    frame_rate: u32,
+    width: u32,
+    height: u32,
+    /// Offset (relative to the "movi" fourcc) and size of every frame chunk.
+    index: Vec<(u32, u32)>,
+    movi_size: u32,
+    max_frame_size: u32,
+    /// First write error, reported when the recording is finished.
+    error: Option<io::Error>,
+}
+
+impl AviWriter {
+    pub(crate) fn create(path: &Path, frame_rate: u32) -> io::Result<AviWriter> {
+        let mut writer = AviWriter {
+            file: BufWriter::new(File::create(path)?),
+            frame_rate: frame_rate.max(1),
+            width: 0,
+            height: 0,
+            index: Vec::new(),
+            movi_size: 0,
+            max_frame_size: 0,
+            error: None,
+        };
+        // Placeholder, rewritten with the final values by finish().
+        let header = writer.header();
+        writer.file.write_all(&header)?;
+        Ok(writer)
+    }
+
+    /// Adds a frame to the recording. Errors are deferred until finish() is called.
+    pub(crate) fn write(&mut self, buffer: &Buffer, image: Option<&Image>) {
+        if self.error.is_some() {
+            return;
+        }
+        let result = if buffer.source_frame_format() == FrameFormat::MJPEG {
+            let resolution = buffer.resolution();
+            self.write_jpeg(buffer.buffer(), resolution.width(), resolution.height())
+        } else if let Some(image) = image {
+            let (data, color_type) = image.encodable();
+            let mut jpeg = Vec::new();
+            let encoded = JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY).encode(
+                &data,
+                image.width,
+                image.height,
+                color_type,
+            );
+            encoded
+                .map_err(io::Error::other)
+                .and_then(|_| self.write_jpeg(&jpeg, image.width, image.height))
+        } else {
+            // Frame couldn't be decoded, nothing to record.
+            Ok(())
+        };
+        if let Err(error) = result {
+            self.error = Some(error);
+        }
+    }
+
+    fn write_jpeg(&mut self, jpeg: &[u8], width: u32, height: u32) -> io::Result<()> {
+        let too_large = || io::Error::other("AVI file size limit reached");
+        let size = u32::try_from(jpeg.len()).map_err(|_| too_large())?;
+        let padding = size % 2;
+        let chunk_size = 8 + size + padding;
+        let new_movi_size = self
+            .movi_size
+            .checked_add(chunk_size)
+            .filter(|movi_size| movi_size.checked_add(HEADER_SIZE + 8).is_some())
+            .ok_or_else(too_large)?;
+        self.file.write_all(b"00dc")?;
+        self.file.write_all(&size.to_le_bytes())?;
+        self.file.write_all(jpeg)?;
+        if padding != 0 {
+            self.file.write_all(&[0])?;
+        }
+        self.index.push((4 + self.movi_size, size));
+        self.movi_size = new_movi_size;
+        self.max_frame_size = self.max_frame_size.max(size);
+        self.width = width;
+        self.height = height;
+        Ok(())
+    }
+
+    /// Writes the index and the final header.
+    pub(crate) fn finish(mut self) -> io::Result<()> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        self.file.write_all(b"idx1")?;
+        self.file
+            .write_all(&(self.index.len() as u32 * 16).to_le_bytes())?;
+        for (offset, size) in &self.index {
+            self.file.write_all(b"00dc")?;
+            self.file.write_all(&AVIIF_KEYFRAME.to_le_bytes())?;
+            self.file.write_all(&offset.to_le_bytes())?;
+            self.file.write_all(&size.to_le_bytes())?;
+        }
+        let header = self.header();
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header)?;
+        self.file.flush()
+    }
+
+    fn header(&self) -> Vec<u8> {
+        let frames = self.index.len() as u32;
+        let riff_size = (HEADER_SIZE - 8)
+            .wrapping_add(self.movi_size)
+            .wrapping_add(8 + frames * 16);
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        let fourcc = |header: &mut Vec<u8>, code: &[u8; 4]| header.extend_from_slice(code);
+        let dword =
+            |header: &mut Vec<u8>, value: u32| header.extend_from_slice(&value.to_le_bytes());
+        let word =
+            |header: &mut Vec<u8>, value: u16| header.extend_from_slice(&value.to_le_bytes());
+
+        fourcc(&mut header, b"RIFF");
+        dword(&mut header, riff_size);
+        fourcc(&mut header, b"AVI ");
+
+        fourcc(&mut header, b"LIST");
+        dword(&mut header, 192);
+        fourcc(&mut header, b"hdrl");
+
+        fourcc(&mut header, b"avih");
+        dword(&mut header, 56);
+        dword(&mut header, 1_000_000 / self.frame_rate);
+        dword(
+            &mut header,
+            self.max_frame_size.saturating_mul(self.frame_rate),
+        );
+        dword(&mut header, 0);
+        dword(&mut header, AVIF_HASINDEX);
+        dword(&mut header, frames);
+        dword(&mut header, 0);
+        dword(&mut header, 1);
+        dword(&mut header, self.max_frame_size);
+        dword(&mut header, self.width);
+        dword(&mut header, self.height);
+        for _ in 0..4 {
+            dword(&mut header, 0);
+        }
+
+        fourcc(&mut header, b"LIST");
+        dword(&mut header, 116);
+        fourcc(&mut header, b"strl");
+
+        fourcc(&mut header, b"strh");
+        dword(&mut header, 56);
+        fourcc(&mut header, b"vids");
+        fourcc(&mut header, b"MJPG");
+        dword(&mut header, 0);
+        word(&mut header, 0);
+        word(&mut header, 0);
+        dword(&mut header, 0);
+        dword(&mut header, 1);
+        dword(&mut header, self.frame_rate);
+        dword(&mut header, 0);
+        dword(&mut header, frames);
+        dword(&mut header, self.max_frame_size);
+        dword(&mut header, u32::MAX);
+        dword(&mut header, 0);
+        word(&mut header, 0);
+        word(&mut header, 0);
+        word(&mut header, self.width as u16);
+        word(&mut header, self.height as u16);
+
+        fourcc(&mut header, b"strf");
+        dword(&mut header, 40);
+        dword(&mut header, 40);
+        dword(&mut header, self.width);
+        dword(&mut header, self.height);
+        word(&mut header, 1);
+        word(&mut header, 24);
+        fourcc(&mut header, b"MJPG");
+        dword(
+            &mut header,
+            self.width.wrapping_mul(self.height).wrapping_mul(3),
+        );
+        for _ in 0..4 {
+            dword(&mut header, 0);
+        }
+
+        fourcc(&mut header, b"LIST");
+        dword(&mut header, 4 + self.movi_size);
+        fourcc(&mut header, b"movi");
+
+        debug_assert_eq!(header.len(), HEADER_SIZE as usize);
+        header
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process};
+
+    use nokhwa::utils::Resolution;
+
+    use super::*;
+
+    fn dword(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn writes_header_and_index() {
+        let path = std::env::temp_dir().join(format!("omni_camera_recorder_{}.avi", process::id()));
+        let mut writer = AviWriter::create(&path, 25).unwrap();
+        // Not valid JPEG, MJPEG frames are stored as they are
+        let frames: [&[u8]; 2] = [&[0xFF, 0xD8, 1, 0xFF, 0xD9], &[0xFF, 0xD8, 0xFF, 0xD9]];
+        for frame in frames {
+            let buffer = Buffer::new(Resolution::new(64, 48), frame, FrameFormat::MJPEG);
+            writer.write(&buffer, None);
+        }
+        writer.finish().unwrap();
+        let data = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(&data[0..4], b"RIFF");
+        assert_eq!(dword(&data, 4) as usize, data.len() - 8);
+        assert_eq!(&data[8..12], b"AVI ");
+        // avih: microseconds per frame, total frames, width and height
+        assert_eq!(&data[24..28], b"avih");
+        assert_eq!(dword(&data, 32), 40_000);
+        assert_eq!(dword(&data, 48), 2);
+        assert_eq!((dword(&data, 64), dword(&data, 68)), (64, 48));
+        let movi = HEADER_SIZE as usize - 4;
+        assert_eq!(&data[movi..movi + 4], b"movi");
+
+        // The first frame is padded to an even size
+        let movi_size = 4 + (8 + 6) + (8 + 4);
+        assert_eq!(dword(&data, movi - 4), movi_size);
+        let idx1 = movi + movi_size as usize;
+        assert_eq!(&data[idx1..idx1 + 4], b"idx1");
+        assert_eq!(dword(&data, idx1 + 4), 2 * 16);
+        for (i, frame) in frames.iter().enumerate() {
+            let entry = idx1 + 8 + i * 16;
+            assert_eq!(&data[entry..entry + 4], b"00dc");
+            assert_eq!(dword(&data, entry + 4), AVIIF_KEYFRAME);
+            let offset = movi + dword(&data, entry + 8) as usize;
+            let size = dword(&data, entry + 12) as usize;
+            assert_eq!(size, frame.len());
+            assert_eq!(&data[offset..offset + 4], b"00dc");
+            assert_eq!(&data[offset + 8..offset + 8 + size], *frame);
+        }
+        assert_eq!(data.len(), idx1 + 8 + 2 * 16);
+    }
+}
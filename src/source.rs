@@ -0,0 +1,217 @@
+use std::{
+    collections::HashMap,
+    thread,
+    time::{Duration, Instant},
+};
+
+use nokhwa::{
+    pixel_format::RgbFormat,
+    utils::{
+        ApiBackend, CameraControl, CameraFormat, CameraIndex, ControlValueSetter,
+        KnownCameraControl, RequestedFormat, RequestedFormatType,
+    },
+    Buffer, NokhwaError,
+};
+
+use crate::{
+    backend::Backend,
+    device::{self, DeviceInfo},
+    test_pattern::{TestPattern, TEST_PATTERN_INDEX_BASE},
+};
+
+/// Something frames can be captured from.
+/// Implemented for cameras opened through nokhwa as well as for built-in virtual sources,
+/// so that the capture thread doesn't need to care where frames come from.
+pub(crate) trait FrameSource: Send {
+    fn camera_format(&mut self) -> CameraFormat;
+    fn compatible_camera_formats(&mut self) -> Result<Vec<CameraFormat>, NokhwaError>;
+    fn set_camera_format(&mut self, format: CameraFormat) -> Result<(), NokhwaError>;
+    fn camera_controls_string(&mut self) -> Result<HashMap<String, CameraControl>, NokhwaError>;
+    fn camera_control(&mut self, id: KnownCameraControl) -> Result<CameraControl, NokhwaError>;
+    fn set_camera_control(
+        &mut self,
+        id: KnownCameraControl,
+        value: ControlValueSetter,
+    ) -> Result<(), NokhwaError>;
+    fn open_stream(&mut self) -> Result<(), NokhwaError>;
+    fn frame(&mut self) -> Result<Buffer, NokhwaError>;
+    fn stop_stream(&mut self) -> Result<(), NokhwaError>;
+
+    /// Changes the format of an open stream. If the new format can't be applied,
+    /// the stream is reopened with the previous one and the error is returned.
+    fn switch_format(&mut self, format: CameraFormat) -> Result<(), NokhwaError> {
+        let previous = self.camera_format();
+        self.stop_stream()?;
+        let result = self.set_camera_format(format);
+        if result.is_err() {
+            let _ = self.set_camera_format(previous);
+        }
+        self.open_stream()?;
+        result
+    }
+
+    /// Whether a finite source (e.g. a recording) has delivered all of its frames.
+    /// The capture thread then stops cleanly instead of treating the failed read as an error.
+    fn at_end(&mut self) -> bool {
+        false
+    }
+
+    /// Whether the device is still present, sources which can't be unplugged always are.
+    fn is_connected(&mut self) -> bool {
+        true
+    }
+
+    /// Reopens the source after it was lost, keeping its format and controls.
+    /// The stream is open afterwards.
+    fn reconnect(&mut self) -> Result<(), NokhwaError> {
+        let _ = self.stop_stream();
+        self.open_stream()
+    }
+}
+
+fn open_camera(index: CameraIndex, api: ApiBackend) -> Result<nokhwa::Camera, NokhwaError> {
+    nokhwa::Camera::with_backend(
+        index,
+        RequestedFormat::new::<RgbFormat>(RequestedFormatType::None),
+        api,
+    )
+}
+
+/// A physical camera opened through nokhwa.
+/// Remembers how it was set up, so that it can be reopened after being unplugged.
+pub(crate) struct DeviceSource {
+    camera: nokhwa::Camera,
+    api: ApiBackend,
+    /// Identifies the device across reconnects, when it may show up at another index.
+    device: DeviceInfo,
+    /// Controls set since the camera was opened, in the order they were set.
+    controls: Vec<(KnownCameraControl, ControlValueSetter)>,
+}
+
+impl DeviceSource {
+    pub(crate) fn open(index: CameraIndex, api: ApiBackend) -> Result<DeviceSource, NokhwaError> {
+        let camera = open_camera(index, api)?;
+        Ok(DeviceSource {
+            device: DeviceInfo::from_camera_info(camera.info(), Backend::Native(api)),
+            api,
+            camera,
+            controls: Vec::new(),
+        })
+    }
+}
+
+impl FrameSource for DeviceSource {
+    fn camera_format(&mut self) -> CameraFormat {
+        self.camera.camera_format()
+    }
+    fn compatible_camera_formats(&mut self) -> Result<Vec<CameraFormat>, NokhwaError> {
+        self.camera.compatible_camera_formats()
+    }
+    fn set_camera_format(&mut self, format: CameraFormat) -> Result<(), NokhwaError> {
+        self.camera.set_camera_format(format)
+    }
+    fn camera_controls_string(&mut self) -> Result<HashMap<String, CameraControl>, NokhwaError> {
+        self.camera.camera_controls_string()
+    }
+    fn camera_control(&mut self, id: KnownCameraControl) -> Result<CameraControl, NokhwaError> {
+        self.camera.camera_control(id)
+    }
+    fn set_camera_control(
+        &mut self,
+        id: KnownCameraControl,
+        value: ControlValueSetter,
+    ) -> Result<(), NokhwaError> {
+        self.camera.set_camera_control(id, value.clone())?;
+        self.controls.retain(|(other, _)| *other != id);
+        self.controls.push((id, value));
+        Ok(())
+    }
+    fn open_stream(&mut self) -> Result<(), NokhwaError> {
+        self.camera.open_stream()
+    }
+    fn frame(&mut self) -> Result<Buffer, NokhwaError> {
+        self.camera.frame()
+    }
+    fn stop_stream(&mut self) -> Result<(), NokhwaError> {
+        self.camera.stop_stream()
+    }
+    fn is_connected(&mut self) -> bool {
+        match &self.device.path {
+            Some(path) => std::path::Path::new(path).exists(),
+            None => true,
+        }
+    }
+    fn reconnect(&mut self) -> Result<(), NokhwaError> {
+        let format = self.camera.camera_format();
+        let stable_id = self.device.stable_id();
+        let device = device::find_device(
+            &stable_id,
+            Some(&self.device.camera_index),
+            self.device.backend,
+        )?;
+        let mut camera = open_camera(device.camera_index.clone(), self.api)?;
+        camera.set_camera_format(format)?;
+        for (id, value) in &self.controls {
+            // Best effort, a control failing to apply shouldn't keep the camera offline
+            let _ = camera.set_camera_control(*id, value.clone());
+        }
+        camera.open_stream()?;
+        self.camera = camera;
+        self.device = device;
+        Ok(())
+    }
+}
+
+/// Opens a source by index, virtual test pattern indices are handled without touching nokhwa.
+/// The test backend also accepts pattern numbers (0, 1, ...) as indices.
+pub(crate) fn open_source(
+    index: CameraIndex,
+    backend: Backend,
+) -> Result<Box<dyn FrameSource>, NokhwaError> {
+    if let CameraIndex::Index(index) = index {
+        let pattern = match backend {
+            Backend::Test if index < TEST_PATTERN_INDEX_BASE => {
+                TestPattern::from_index(TEST_PATTERN_INDEX_BASE + index)
+            }
+            _ => TestPattern::from_index(index),
+        };
+        if let Some(pattern) = pattern {
+            return Ok(Box::new(pattern));
+        }
+    }
+    match backend {
+        Backend::Native(api) => Ok(Box::new(DeviceSource::open(index, api)?)),
+        Backend::Test => Err(NokhwaError::OpenDeviceError(
+            index.as_string(),
+            "No such test pattern".to_string(),
+        )),
+    }
+}
+
+/// Spaces out frames of virtual sources according to the selected frame rate.
+pub(crate) struct FramePacer {
+    next_frame_at: Instant,
+}
+
+impl FramePacer {
+    pub(crate) fn new() -> FramePacer {
+        FramePacer {
+            next_frame_at: Instant::now(),
+        }
+    }
+    pub(crate) fn reset(&mut self) {
+        self.next_frame_at = Instant::now();
+    }
+    /// Sleeps until it's time to produce the next frame.
+    pub(crate) fn wait(&mut self, frame_rate: u32) {
+        let interval = Duration::from_secs(1) / frame_rate.max(1);
+        let now = Instant::now();
+        if self.next_frame_at > now {
+            thread::sleep(self.next_frame_at - now);
+        } else if now - self.next_frame_at > interval {
+            // Fell behind (e.g. nobody was reading frames), don't try to catch up.
+            self.next_frame_at = now;
+        }
+        self.next_frame_at += interval;
+    }
+}
@@ -0,0 +1,145 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use nokhwa::NokhwaError;
+use pyo3::prelude::*;
+
+/// Number of recent frames frame rate, jitter and decode time are measured over.
+const WINDOW: usize = 60;
+
+/// Measurements taken by the capture thread since the camera was opened.
+#[derive(Default)]
+pub(crate) struct StatsCollector {
+    frames: u64,
+    read_errors: u64,
+    decode_errors: u64,
+    last_error: Option<String>,
+    /// The most recent read or decode failure not raised by check_err yet.
+    frame_error: Option<NokhwaError>,
+    last_captured_at: Option<Instant>,
+    /// Time between consecutive frames, most recent last.
+    intervals: VecDeque<Duration>,
+    /// Time spent decoding frames, most recent last.
+    decode_times: VecDeque<Duration>,
+}
+
+fn push_limited(window: &mut VecDeque<Duration>, value: Duration) {
+    if window.len() == WINDOW {
+        window.pop_front();
+    }
+    window.push_back(value);
+}
+
+fn mean(window: &VecDeque<Duration>) -> Option<f64> {
+    if window.is_empty() {
+        return None;
+    }
+    Some(window.iter().map(Duration::as_secs_f64).sum::<f64>() / window.len() as f64)
+}
+
+impl StatsCollector {
+    pub(crate) fn read_failed(&mut self, error: &NokhwaError) {
+        self.read_errors += 1;
+        self.last_error = Some(error.to_string());
+        self.frame_error = Some(error.clone());
+    }
+    pub(crate) fn decode_failed(&mut self, error: &NokhwaError) {
+        self.decode_errors += 1;
+        self.last_error = Some(error.to_string());
+        self.frame_error = Some(error.clone());
+    }
+    /// Takes the most recent read or decode failure, so that it's only reported once.
+    pub(crate) fn take_frame_error(&mut self) -> Option<NokhwaError> {
+        self.frame_error.take()
+    }
+    /// Records a frame delivered by the capture thread. `decode_time` is None if decoding is turned off.
+    pub(crate) fn frame(&mut self, captured_at: Instant, decode_time: Option<Duration>) {
+        self.frames += 1;
+        if let Some(previous) = self.last_captured_at.replace(captured_at) {
+            push_limited(&mut self.intervals, captured_at - previous);
+        }
+        if let Some(decode_time) = decode_time {
+            push_limited(&mut self.decode_times, decode_time);
+        }
+    }
+    pub(crate) fn snapshot(&self, dropped: u64, overwritten: u64) -> CaptureStats {
+        let interval = mean(&self.intervals);
+        let jitter = interval.map(|interval| {
+            let variance = self
+                .intervals
+                .iter()
+                .map(|x| (x.as_secs_f64() - interval).powi(2))
+                .sum::<f64>()
+                / self.intervals.len() as f64;
+            variance.sqrt()
+        });
+        CaptureStats {
+            frames_captured: self.frames,
+            frames_dropped: dropped,
+            frames_overwritten: overwritten,
+            fps: interval.filter(|&interval| interval > 0.0).map(|x| 1.0 / x),
+            interval_jitter: jitter,
+            decode_time: mean(&self.decode_times),
+            read_errors: self.read_errors,
+            decode_errors: self.decode_errors,
+            last_error: self.last_error.clone(),
+        }
+    }
+}
+
+/// Capture statistics since the camera was opened, returned by Camera.stats.
+/// Rates and timings (in seconds) are measured over the last few frames, None until there are enough of them.
+#[pyclass]
+pub(crate) struct CaptureStats {
+    /// Frames delivered by the capture thread.
+    #[pyo3(get)]
+    frames_captured: u64,
+    /// Frames evicted from a full queue before being read.
+    #[pyo3(get)]
+    frames_dropped: u64,
+    /// Frames replaced by a newer one before any consumer (poll_frame/wait_frame, the queue or a frame handler) took them.
+    #[pyo3(get)]
+    frames_overwritten: u64,
+    /// Measured rate at which frames are delivered.
+    #[pyo3(get)]
+    fps: Option<f64>,
+    /// Standard deviation of the time between frames.
+    #[pyo3(get)]
+    interval_jitter: Option<f64>,
+    /// Average time spent decoding a frame.
+    #[pyo3(get)]
+    decode_time: Option<f64>,
+    /// Frames which couldn't be read from the camera.
+    #[pyo3(get)]
+    read_errors: u64,
+    /// Frames which were read but couldn't be decoded, these are skipped.
+    #[pyo3(get)]
+    decode_errors: u64,
+    /// Description of the most recent failure.
+    #[pyo3(get)]
+    last_error: Option<String>,
+}
+
+fn format_optional(value: Option<f64>) -> String {
+    value.map_or("None".to_string(), |value| format!("{value:.4}"))
+}
+
+#[pymethods]
+impl CaptureStats {
+    fn __repr__(&self) -> String {
+        format!(
+            "CaptureStats(frames_captured={}, frames_dropped={}, frames_overwritten={}, fps={}, interval_jitter={}, decode_time={}, read_errors={}, decode_errors={}, last_error={:?})",
+            self.frames_captured,
+            self.frames_dropped,
+            self.frames_overwritten,
+            format_optional(self.fps),
+            format_optional(self.interval_jitter),
+            format_optional(self.decode_time),
+            self.read_errors,
+            self.decode_errors,
+            self.last_error
+        )
+    }
+}
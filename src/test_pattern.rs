@@ -0,0 +1,321 @@
+use std::collections::HashMap;
+
+use image::{codecs::jpeg::JpegEncoder, ColorType};
+use nokhwa::{
+    utils::{
+        CameraControl, CameraFormat, ControlValueDescription, ControlValueSetter, FrameFormat,
+        KnownCameraControl, KnownCameraControlFlag,
+    },
+    Buffer, NokhwaError,
+};
+
+use crate::{
+    pixel::rgb_to_yuyv,
+    source::{FramePacer, FrameSource},
+};
+
+/// Indices at and above this one are reserved for virtual test pattern cameras.
+pub(crate) const TEST_PATTERN_INDEX_BASE: u32 = 0xFFFF_0000;
+
+const RESOLUTIONS: [(u32, u32); 3] = [(320, 240), (640, 480), (1280, 720)];
+const FRAME_RATES: [u32; 3] = [15, 30, 60];
+const FRAME_FORMATS: [FrameFormat; 2] = [FrameFormat::MJPEG, FrameFormat::YUYV];
+
+const BRIGHTNESS_RANGE: (i64, i64) = (-64, 64);
+const CONTRAST_RANGE: (i64, i64) = (0, 200);
+const DEFAULT_CONTRAST: i64 = 100;
+
+const BARS: [[u8; 3]; 7] = [
+    [255, 255, 255],
+    [255, 255, 0],
+    [0, 255, 255],
+    [0, 255, 0],
+    [255, 0, 255],
+    [255, 0, 0],
+    [0, 0, 255],
+];
+
+/// 3x5 bitmaps for digits, used to burn the frame counter into the image.
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b001, 0b001],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+#[derive(Clone, Copy)]
+pub(crate) enum Pattern {
+    ColorBars,
+    Gradient,
+}
+
+impl Pattern {
+    const ALL: [Pattern; 2] = [Pattern::ColorBars, Pattern::Gradient];
+
+    fn name(self) -> &'static str {
+        match self {
+            Pattern::ColorBars => "Test pattern (colour bars)",
+            Pattern::Gradient => "Test pattern (moving gradient)",
+        }
+    }
+}
+
+/// A virtual camera which generates frames on its own, useful for testing without hardware.
+/// Every frame has a frame counter burned into its top left corner.
+pub(crate) struct TestPattern {
+    pattern: Pattern,
+    format: CameraFormat,
+    stream_open: bool,
+    pacer: FramePacer,
+    counter: u64,
+    brightness: i64,
+    contrast: i64,
+}
+
+impl TestPattern {
+    pub(crate) fn new(pattern: Pattern) -> TestPattern {
+        TestPattern {
+            pattern,
+            format: CameraFormat::new_from(640, 480, FrameFormat::MJPEG, 30),
+            stream_open: false,
+            pacer: FramePacer::new(),
+            counter: 0,
+            brightness: 0,
+            contrast: DEFAULT_CONTRAST,
+        }
+    }
+
+    pub(crate) fn from_index(index: u32) -> Option<TestPattern> {
+        let offset = index.checked_sub(TEST_PATTERN_INDEX_BASE)?;
+        Pattern::ALL
+            .get(offset as usize)
+            .map(|pattern| TestPattern::new(*pattern))
+    }
+
+    /// Index and name of every available test pattern.
+    pub(crate) fn devices() -> impl Iterator<Item = (u32, &'static str)> {
+        Pattern::ALL
+            .iter()
+            .enumerate()
+            .map(|(i, pattern)| (TEST_PATTERN_INDEX_BASE + i as u32, pattern.name()))
+    }
+
+    fn formats() -> Vec<CameraFormat> {
+        let mut formats = Vec::new();
+        for format in FRAME_FORMATS {
+            for (width, height) in RESOLUTIONS {
+                for frame_rate in FRAME_RATES {
+                    formats.push(CameraFormat::new_from(width, height, format, frame_rate));
+                }
+            }
+        }
+        formats
+    }
+
+    fn adjust(&self, value: u8) -> u8 {
+        ((value as i64 - 128) * self.contrast / 100 + 128 + self.brightness).clamp(0, 255) as u8
+    }
+
+    fn render(&self) -> Vec<u8> {
+        let width = self.format.width() as usize;
+        let height = self.format.height() as usize;
+        let shift = self.counter as usize * 4;
+        let mut rgb = vec![0; width * height * 3];
+        for y in 0..height {
+            for x in 0..width {
+                let color = match self.pattern {
+                    Pattern::ColorBars => BARS[x * BARS.len() / width],
+                    Pattern::Gradient => {
+                        let t = (x + shift) % width * 255 / width;
+                        [t as u8, (y * 255 / height) as u8, (255 - t) as u8]
+                    }
+                };
+                let offset = (y * width + x) * 3;
+                for (out, value) in rgb[offset..offset + 3].iter_mut().zip(color) {
+                    *out = self.adjust(value);
+                }
+            }
+        }
+        draw_counter(&mut rgb, width, height, self.counter);
+        rgb
+    }
+
+    fn encode(&self, rgb: &[u8]) -> Result<Vec<u8>, NokhwaError> {
+        match self.format.format() {
+            FrameFormat::MJPEG => {
+                let mut out = Vec::new();
+                JpegEncoder::new_with_quality(&mut out, 90)
+                    .encode(
+                        rgb,
+                        self.format.width(),
+                        self.format.height(),
+                        ColorType::Rgb8,
+                    )
+                    .map_err(|error| NokhwaError::GeneralError(error.to_string()))?;
+                Ok(out)
+            }
+            FrameFormat::YUYV => Ok(rgb_to_yuyv(rgb)),
+            other => Err(NokhwaError::GeneralError(format!(
+                "Test pattern can't produce {other:?} frames"
+            ))),
+        }
+    }
+
+    fn control(
+        id: KnownCameraControl,
+        name: &str,
+        value: i64,
+        (min, max): (i64, i64),
+        default: i64,
+    ) -> CameraControl {
+        CameraControl::new(
+            id,
+            name.to_string(),
+            ControlValueDescription::IntegerRange {
+                min,
+                max,
+                value,
+                step: 1,
+                default,
+            },
+            vec![KnownCameraControlFlag::Manual],
+            true,
+        )
+    }
+}
+
+impl FrameSource for TestPattern {
+    fn camera_format(&mut self) -> CameraFormat {
+        self.format
+    }
+    fn compatible_camera_formats(&mut self) -> Result<Vec<CameraFormat>, NokhwaError> {
+        Ok(TestPattern::formats())
+    }
+    fn set_camera_format(&mut self, format: CameraFormat) -> Result<(), NokhwaError> {
+        let error = if self.stream_open {
+            "Can't change format while the stream is open"
+        } else if !TestPattern::formats().contains(&format) {
+            "Unsupported format"
+        } else {
+            self.format = format;
+            return Ok(());
+        };
+        Err(NokhwaError::SetPropertyError {
+            property: "CameraFormat".to_string(),
+            value: format!("{format:?}"),
+            error: error.to_string(),
+        })
+    }
+    fn camera_controls_string(&mut self) -> Result<HashMap<String, CameraControl>, NokhwaError> {
+        let controls = [
+            TestPattern::control(
+                KnownCameraControl::Brightness,
+                "Brightness",
+                self.brightness,
+                BRIGHTNESS_RANGE,
+                0,
+            ),
+            TestPattern::control(
+                KnownCameraControl::Contrast,
+                "Contrast",
+                self.contrast,
+                CONTRAST_RANGE,
+                DEFAULT_CONTRAST,
+            ),
+        ];
+        Ok(controls
+            .into_iter()
+            .map(|control| (control.name().to_string(), control))
+            .collect())
+    }
+    fn camera_control(&mut self, id: KnownCameraControl) -> Result<CameraControl, NokhwaError> {
+        self.camera_controls_string()?
+            .into_values()
+            .find(|control| control.control() == id)
+            .ok_or_else(|| NokhwaError::GetPropertyError {
+                property: format!("{id:?}"),
+                error: "Unsupported control".to_string(),
+            })
+    }
+    fn set_camera_control(
+        &mut self,
+        id: KnownCameraControl,
+        value: ControlValueSetter,
+    ) -> Result<(), NokhwaError> {
+        let (target, (min, max)) = match id {
+            KnownCameraControl::Brightness => (&mut self.brightness, BRIGHTNESS_RANGE),
+            KnownCameraControl::Contrast => (&mut self.contrast, CONTRAST_RANGE),
+            _ => {
+                return Err(NokhwaError::SetPropertyError {
+                    property: format!("{id:?}"),
+                    value: format!("{value:?}"),
+                    error: "Unsupported control".to_string(),
+                })
+            }
+        };
+        match value {
+            ControlValueSetter::Integer(value) if (min..=max).contains(&value) => {
+                *target = value;
+                Ok(())
+            }
+            _ => Err(NokhwaError::SetPropertyError {
+                property: format!("{id:?}"),
+                value: format!("{value:?}"),
+                error: format!("Expected an integer in {min}..={max}"),
+            }),
+        }
+    }
+    fn open_stream(&mut self) -> Result<(), NokhwaError> {
+        self.stream_open = true;
+        self.pacer.reset();
+        Ok(())
+    }
+    fn frame(&mut self) -> Result<Buffer, NokhwaError> {
+        if !self.stream_open {
+            return Err(NokhwaError::ReadFrameError(
+                "Stream is not open".to_string(),
+            ));
+        }
+        self.pacer.wait(self.format.frame_rate());
+        self.counter += 1;
+        let data = self.encode(&self.render())?;
+        Ok(Buffer::new(
+            self.format.resolution(),
+            &data,
+            self.format.format(),
+        ))
+    }
+    fn stop_stream(&mut self) -> Result<(), NokhwaError> {
+        self.stream_open = false;
+        Ok(())
+    }
+}
+
+fn draw_counter(rgb: &mut [u8], width: usize, height: usize, counter: u64) {
+    let digits: Vec<usize> = counter
+        .to_string()
+        .bytes()
+        .map(|digit| (digit - b'0') as usize)
+        .collect();
+    let scale = (height / 60).max(1);
+    // One cell of margin around the text, one cell of spacing between digits.
+    let columns = digits.len() * 4 + 1;
+    for y in 0..(7 * scale).min(height) {
+        for x in 0..(columns * scale).min(width) {
+            let (column, row) = (x / scale, y / scale);
+            let lit = column >= 1
+                && (1..=5).contains(&row)
+                && (column - 1) % 4 < 3
+                && (DIGITS[digits[(column - 1) / 4]][row - 1] >> (2 - (column - 1) % 4)) & 1 == 1;
+            let value = if lit { 255 } else { 0 };
+            let offset = (y * width + x) * 3;
+            rgb[offset..offset + 3].fill(value);
+        }
+    }
+}
@@ -0,0 +1,62 @@
+import pytest
+
+import omni_camera
+
+
+def test_test_pattern_frames():
+    cam = omni_camera.Camera(0, backend="test")
+    cam.open()
+    try:
+        assert cam.state == omni_camera.StreamState.STREAMING
+        first = cam.wait_frame(5)
+        second = cam.wait_frame(5)
+        assert second.sequence > first.sequence
+        fmt = cam.get_format()
+        assert (second.width, second.height) == (fmt.width, fmt.height)
+        assert len(second.data) == fmt.width * fmt.height * 3
+    finally:
+        cam.close()
+    assert cam.state == omni_camera.StreamState.CLOSED
+
+
+def write_recording(path):
+    """Two 2x2 frames of packed rgb values."""
+    path.write_bytes(bytes([1]) * 12 + bytes([2]) * 12)
+
+
+def test_open_in_unsupported_format_raises(tmp_path):
+    path = tmp_path / "frames.rgb"
+    write_recording(path)
+    fmt = omni_camera.Camera.from_file(path, resolution=(2, 2)).get_format()
+    cam = omni_camera.Camera(0, backend="test")
+    with pytest.raises(omni_camera.UnsupportedFormatError):
+        cam.open(fmt)
+    assert cam.state == omni_camera.StreamState.CLOSED
+
+
+def test_playback_ends_cleanly(tmp_path):
+    path = tmp_path / "frames.rgb"
+    write_recording(path)
+    cam = omni_camera.Camera.from_file(path, frame_rate=1000, resolution=(2, 2))
+    cam.open(queue_size=8)
+    frames = []
+    while (frame := cam.next_frame(5)) is not None:
+        frames.append(frame)
+    assert [frame.data for frame in frames] == [bytes([1]) * 12, bytes([2]) * 12]
+    assert cam.state == omni_camera.StreamState.CLOSED
+    assert cam.stats().read_errors == 0
+    with pytest.raises(omni_camera.StreamClosedError):
+        cam.next_frame(5)
+
+
+def test_open_without_decode_requires_keep_raw():
+    cam = omni_camera.Camera(0, backend="test")
+    with pytest.raises(ValueError):
+        cam.open(decode=False)
+    assert cam.state == omni_camera.StreamState.CLOSED
+    cam.open(decode=False, keep_raw=True)
+    try:
+        frame = cam.wait_frame(5)
+        assert frame.raw_data
+    finally:
+        cam.close()
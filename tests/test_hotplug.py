@@ -0,0 +1,48 @@
+import asyncio
+import threading
+import time
+
+import omni_camera
+
+
+def test_fake_device_directory(tmp_path):
+    (tmp_path / "video0").touch()
+    (tmp_path / "media0").touch()
+    with omni_camera.DeviceWatcher(interval=0.01, dev_dir=tmp_path) as watcher:
+        assert [camera.path for camera in watcher.cameras] == [str(tmp_path / "video0")]
+        (tmp_path / "video1").touch()
+        event = watcher.wait_event(5)
+        assert (event.kind, event.camera.path) == ("added", str(tmp_path / "video1"))
+        (tmp_path / "video0").unlink()
+        event = watcher.wait_event(5)
+        assert (event.kind, event.camera.path) == ("removed", str(tmp_path / "video0"))
+        assert watcher.poll_event() is None
+    assert watcher.wait_event() is None
+
+
+def test_close_wakes_up_waiters(tmp_path):
+    watcher = omni_camera.DeviceWatcher(interval=0.01, dev_dir=tmp_path)
+    results = []
+    waiter = threading.Thread(target=lambda: results.append(watcher.wait_event()))
+    waiter.start()
+    time.sleep(0.1)
+    watcher.close()
+    waiter.join(5)
+    assert results == [None]
+
+
+def test_event_stream_ends_when_closed(tmp_path):
+    async def main():
+        watcher = omni_camera.DeviceWatcher(interval=0.01, dev_dir=tmp_path)
+        events = []
+        async with watcher.events() as stream:
+            (tmp_path / "video0").touch()
+            async for event in stream:
+                events.append(event.kind)
+                await asyncio.get_running_loop().run_in_executor(None, watcher.close)
+        assert events == ["added"]
+        # Streams of a closed watcher end right away
+        async with watcher.events() as stream:
+            assert [event async for event in stream] == []
+
+    asyncio.run(asyncio.wait_for(main(), 10))
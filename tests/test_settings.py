@@ -0,0 +1,102 @@
+import math
+
+import pytest
+
+import omni_camera
+from omni_camera.settings import SETTINGS_VERSION, apply_settings, load_settings, save_settings
+
+SETTINGS = {
+    "version": SETTINGS_VERSION,
+    "format": {"width": 640, "height": 480, "frame_rate": 30, "frame_format": "mjpeg"},
+    "controls": {
+        "Brightness": {"kind": "integer_range", "value": -12, "active": True},
+        "White Balance, Auto": {"kind": "boolean", "value": False, "active": True},
+        "Gamma": {"kind": "float_range", "value": 2.5, "active": False},
+        "Zoom": {"kind": "float", "value": math.inf, "active": True},
+        'Name "quoted"': {"kind": "string", "value": "a \"b\"\n", "active": True},
+        "LUT": {"kind": "bytes", "value": "00ff10", "active": True},
+        "Focus Point": {"kind": "point", "value": [0.25, 0.75], "active": True},
+        "Unreadable": {"kind": "none", "value": None, "active": True},
+    },
+}
+
+
+def has_toml_reader():
+    try:
+        import tomllib
+    except ImportError:
+        try:
+            import tomli
+        except ImportError:
+            return False
+    return True
+
+
+@pytest.mark.parametrize("suffix", [".json", ".toml"])
+def test_round_trip(tmp_path, suffix):
+    if suffix == ".toml" and not has_toml_reader():
+        pytest.skip("Reading TOML requires Python 3.11+ or tomli")
+    path = tmp_path / f"settings{suffix}"
+    save_settings(SETTINGS, path)
+    assert load_settings(path) == SETTINGS
+
+
+def test_apply_to_test_pattern():
+    cam = omni_camera.Camera(0, backend="test")
+    cam.open()
+    try:
+        settings = cam.export_settings()
+        fmt = next(x for x in cam.get_format_options() if x.width != cam.get_format().width)
+        settings["format"]["width"] = fmt.width
+        settings["format"]["height"] = fmt.height
+        settings["format"]["frame_rate"] = fmt.frame_rate
+        settings["format"]["frame_format"] = fmt.frame_format.value
+        settings["controls"]["Brightness"]["value"] = 10
+        assert cam.apply_settings(settings) == {}
+        assert cam.get_format().width == fmt.width
+        assert cam.get_controls()["Brightness"].value == 10
+
+        settings["format"]["width"] = 123
+        settings["controls"]["Brightness"]["value"] = 20
+        settings["controls"]["Brightness"]["active"] = False
+        errors = cam.apply_settings(settings)
+        assert set(errors) == {"format"}
+        # Inactive controls are left to their automatic counterpart
+        assert cam.get_controls()["Brightness"].value == 10
+    finally:
+        cam.close()
+
+
+class FakeControl:
+    flags = []
+
+    def __init__(self, name, kind, applied):
+        self.name = name
+        self.kind = kind
+        self._applied = applied
+
+    def set_value(self, value):
+        self._applied.append((self.name, value))
+
+
+class FakeCamera:
+    def __init__(self, kinds):
+        self.applied = []
+        self._controls = {name: FakeControl(name, kind, self.applied) for name, kind in kinds.items()}
+
+    def get_controls(self):
+        return self._controls
+
+
+def test_apply_sets_mode_controls_first_and_skips_inactive_ones():
+    cam = FakeCamera({"Exposure": "integer_range", "Gain": "integer_range", "Exposure, Auto": "enum"})
+    settings = {
+        "version": SETTINGS_VERSION,
+        "controls": {
+            "Exposure": {"kind": "integer_range", "value": 100, "active": True},
+            "Gain": {"kind": "integer_range", "value": 5, "active": False},
+            "Exposure, Auto": {"kind": "enum", "value": 1, "active": True},
+        },
+    }
+    assert apply_settings(cam, settings) == {}
+    assert cam.applied == [("Exposure, Auto", 1), ("Exposure", 100)]